@@ -0,0 +1,141 @@
+extern crate serialize;
+
+use std::vec::Vec;
+use std::io::File;
+
+use serialize::json;
+
+/// Label of the category given to ocean vertices.
+pub const OCEAN_LABEL: &'static str = "ocean";
+
+/// Label of the category given to lake vertices. Lake beds sit under
+/// standing water, so the climate of their shores does not apply.
+pub const LAKE_LABEL: &'static str = "lake";
+
+/// Single row of a Whittaker-style lookup table.
+#[deriving(Clone, Show, Decodable)]
+pub struct BiomeRule {
+    pub name: String,
+    /// Upper bound of mean annual temperature, in degrees Celsius. No
+    /// bound if missing.
+    pub max_temp_c: Option<f32>,
+    /// Lower bound of annual precipitation, in mm.
+    pub min_precipitation_mm: f32,
+    /// Color in the viewer, as [red, green, blue] in [0, 1].
+    pub color: Vec<f32>
+}
+
+fn rule(name: &str,
+        max_temp_c: Option<f32>,
+        min_precipitation_mm: f32,
+        color: [f32, ..3]) -> BiomeRule {
+    BiomeRule {
+        name: name.to_string(),
+        max_temp_c: max_temp_c,
+        min_precipitation_mm: min_precipitation_mm,
+        color: color.to_vec()
+    }
+}
+
+/// Biome lookup by temperature and precipitation. A land vertex gets the
+/// biome of the first rule it satisfies. Can be loaded from a JSON file,
+/// e.g.:
+///
+/// ```json
+/// { "biomes": [ { "name": "ice", "max_temp_c": -10,
+///                 "min_precipitation_mm": 0, "color": [0.9, 0.9, 1.0] },
+///               { "name": "desert",
+///                 "min_precipitation_mm": 0, "color": [0.8, 0.7, 0.4] } ] }
+/// ```
+#[deriving(Clone, Show, Decodable)]
+pub struct BiomeTable {
+    pub biomes: Vec<BiomeRule>
+}
+
+impl BiomeTable {
+    /// Simplified Whittaker diagram.
+    pub fn whittaker() -> BiomeTable {
+        BiomeTable {
+            biomes: vec![
+                rule("ice",                      Some(-10.0),    0.0, [0.9, 0.9, 0.95]),
+                rule("tundra",                   Some(0.0),      0.0, [0.6, 0.6, 0.5]),
+                rule("taiga",                    Some(5.0),    250.0, [0.2, 0.4, 0.3]),
+                rule("cold desert",              Some(5.0),      0.0, [0.6, 0.55, 0.45]),
+                rule("temperate rainforest",     Some(20.0),  2000.0, [0.05, 0.4, 0.25]),
+                rule("temperate forest",         Some(20.0),   750.0, [0.2, 0.5, 0.2]),
+                rule("grassland",                Some(20.0),   250.0, [0.55, 0.65, 0.3]),
+                rule("desert",                   Some(20.0),     0.0, [0.8, 0.7, 0.45]),
+                rule("tropical rainforest",      None,        2500.0, [0.0, 0.35, 0.1]),
+                rule("tropical seasonal forest", None,        1500.0, [0.3, 0.5, 0.1]),
+                rule("savanna",                  None,         500.0, [0.7, 0.65, 0.3]),
+                rule("desert",                   None,           0.0, [0.85, 0.75, 0.45])
+            ]
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<BiomeTable, String> {
+        let text = match File::open(path).read_to_string() {
+            Ok(text) => text,
+            Err(e) => return Err(format!("cannot read {}: {}", path.display(), e))
+        };
+
+        let table = match json::decode::<BiomeTable>(text.as_slice()) {
+            Ok(table) => table,
+            Err(e) => return Err(format!("invalid biome table {}: {}", path.display(), e))
+        };
+
+        if table.biomes.is_empty() {
+            return Err(format!("biome table {} lists no biomes", path.display()));
+        }
+        for biome in table.biomes.iter() {
+            if biome.color.len() != 3 {
+                return Err(format!("biome {}: expected [red, green, blue], got {}",
+                                   biome.name, biome.color));
+            }
+        }
+        Ok(table)
+    }
+
+    /// Category labels: ocean first, lake second, then every distinct
+    /// biome name in table order.
+    pub fn labels(&self) -> Vec<String> {
+        let mut labels = vec![OCEAN_LABEL.to_string(), LAKE_LABEL.to_string()];
+        for biome in self.biomes.iter() {
+            if !labels.contains(&biome.name) {
+                labels.push(biome.name.clone());
+            }
+        }
+        labels
+    }
+
+    /// Color of every label, in the order of `labels`. Biomes listed more
+    /// than once take the color of their first rule.
+    pub fn colors(&self,
+                  ocean_color: [f32, ..4],
+                  lake_color: [f32, ..4]) -> Vec<[f32, ..4]> {
+        let labels = self.labels();
+        let mut colors = vec![ocean_color, lake_color];
+        for label in labels.iter().skip(2) {
+            let biome = self.biomes.iter().find(|b| &b.name == label).unwrap();
+            colors.push([biome.color[0], biome.color[1], biome.color[2], 1.0]);
+        }
+        colors
+    }
+
+    /// Label index of the biome at given temperature and precipitation.
+    /// Land matching no rule is counted with the last one.
+    pub fn classify(&self,
+                    labels: &Vec<String>,
+                    temp_c: f32,
+                    precipitation_mm: f32) -> uint {
+        let biome = self.biomes.iter().find(|b| {
+            b.max_temp_c.map_or(true, |max| temp_c < max)
+                && precipitation_mm >= b.min_precipitation_mm
+        });
+        let name = match biome {
+            Some(biome) => &biome.name,
+            None => &self.biomes[self.biomes.len() - 1].name
+        };
+        labels.iter().position(|label| label == name).unwrap()
+    }
+}
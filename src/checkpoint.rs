@@ -0,0 +1,142 @@
+extern crate cgmath;
+
+use std::io::{File, IoResult, IoError, InvalidInput, BufferedReader, BufferedWriter};
+
+use cgmath::Vector3;
+
+use plate_simulation::PlateSimulation;
+use sim_rng::SimRng;
+
+const MAGIC: &'static [u8] = b"WRLDCKPT";
+const VERSION: u32 = 5;
+
+pub fn invalid_data(desc: &'static str) -> IoError {
+    IoError {
+        kind: InvalidInput,
+        desc: desc,
+        detail: None
+    }
+}
+
+/// Stores complete plate simulation state together with the random number
+/// generator, so that a run can be resumed bit-identically.
+pub fn save(path: &Path,
+            sim: &PlateSimulation,
+            rng: &SimRng) -> IoResult<()> {
+    let mut w = BufferedWriter::new(try!(File::create(path)));
+
+    try!(w.write(MAGIC));
+    try!(w.write_le_u32(VERSION));
+    try!(rng.write_to(&mut w));
+    try!(sim.write_to(&mut w));
+    w.flush()
+}
+
+pub fn load(path: &Path) -> IoResult<(PlateSimulation, SimRng)> {
+    let mut r = BufferedReader::new(try!(File::open(path)));
+
+    if try!(r.read_exact(MAGIC.len())).as_slice() != MAGIC {
+        return Err(invalid_data("not a plate simulation checkpoint"));
+    }
+    if try!(r.read_le_u32()) != VERSION {
+        return Err(invalid_data("unsupported checkpoint version"));
+    }
+
+    let rng = try!(SimRng::read_from(&mut r));
+    let sim = try!(PlateSimulation::read_from(&mut r));
+    Ok((sim, rng))
+}
+
+pub fn write_uint<W: Writer>(w: &mut W, x: uint) -> IoResult<()> {
+    w.write_le_u64(x as u64)
+}
+
+pub fn read_uint<R: Reader>(r: &mut R) -> IoResult<uint> {
+    r.read_le_u64().map(|x| x as uint)
+}
+
+pub fn write_bool<W: Writer>(w: &mut W, x: bool) -> IoResult<()> {
+    w.write_u8(x as u8)
+}
+
+pub fn read_bool<R: Reader>(r: &mut R) -> IoResult<bool> {
+    r.read_u8().map(|x| x != 0)
+}
+
+pub fn write_vec3<W: Writer>(w: &mut W, v: &Vector3<f32>) -> IoResult<()> {
+    try!(w.write_le_f32(v.x));
+    try!(w.write_le_f32(v.y));
+    w.write_le_f32(v.z)
+}
+
+pub fn read_vec3<R: Reader>(r: &mut R) -> IoResult<Vector3<f32>> {
+    let x = try!(r.read_le_f32());
+    let y = try!(r.read_le_f32());
+    let z = try!(r.read_le_f32());
+    Ok(Vector3::new(x, y, z))
+}
+
+pub fn write_uint_vec<W: Writer>(w: &mut W, v: &Vec<uint>) -> IoResult<()> {
+    try!(write_uint(w, v.len()));
+    for &x in v.iter() {
+        try!(write_uint(w, x));
+    }
+    Ok(())
+}
+
+pub fn read_uint_vec<R: Reader>(r: &mut R) -> IoResult<Vec<uint>> {
+    let len = try!(read_uint(r));
+    // len is untrusted: grow while reading rather than reserving up front
+    let mut v = Vec::new();
+    for _ in range(0u, len) {
+        v.push(try!(read_uint(r)));
+    }
+    Ok(v)
+}
+
+#[cfg(test)]
+mod test {
+    use std::os;
+    use std::rand::SeedableRng;
+    use std::io::MemWriter;
+    use std::io::fs;
+
+    use polyhedron::make_sphere;
+    use plate_simulation::{PlateSimulation, PlateSimParams};
+    use sim_rng::SimRng;
+
+    fn state_of(sim: &PlateSimulation) -> Vec<u8> {
+        let mut w = MemWriter::new();
+        sim.write_to(&mut w).unwrap();
+        w.get_ref().to_vec()
+    }
+
+    #[test]
+    fn resumed_run_matches_uninterrupted_run() {
+        const NUM_STEPS: uint = 6;
+        const SAVED_AT: uint = 2;
+
+        let poly = make_sphere(2);
+        let params = PlateSimParams {
+            convection_interval: 2,
+            num_hotspots: 2,
+            ..PlateSimParams::new(5)
+        };
+        let mut rng: SimRng = SeedableRng::from_seed([1u32, 2, 3, 4]);
+        let mut straight = PlateSimulation::new(&poly, &params, &mut rng.clone());
+        let mut interrupted = PlateSimulation::new(&poly, &params, &mut rng);
+
+        straight.simulate_plates(NUM_STEPS);
+        interrupted.simulate_plates(SAVED_AT);
+
+        let path = os::tmpdir().join("world-rs-checkpoint-test");
+        super::save(&path, &interrupted, &rng).unwrap();
+        let (mut resumed, resumed_rng) = super::load(&path).unwrap();
+        fs::unlink(&path).unwrap();
+
+        assert!(resumed_rng == rng);
+        resumed.simulate_plates(NUM_STEPS - SAVED_AT);
+        assert_eq!(resumed.step(), NUM_STEPS);
+        assert!(state_of(&resumed) == state_of(&straight));
+    }
+}
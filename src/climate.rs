@@ -0,0 +1,392 @@
+extern crate cgmath;
+
+use std::vec::Vec;
+use std::num::{Float, FloatMath};
+use std::f32::consts::PI;
+
+use cgmath::{EuclideanVector, Vector, Vector3};
+
+use domain::Topology;
+use geo::lat_lon_deg;
+
+/// Axial tilt of the Earth, in degrees.
+pub const EARTH_AXIAL_TILT_DEG: f32 = 23.44;
+
+/// Mean annual insolation at latitude `lat_deg` relative to the planet
+/// average, for a planet with given axial tilt. Second order Legendre
+/// approximation of the annual mean (North, 1975).
+fn relative_insolation(lat_deg: f32, axial_tilt_deg: f32) -> f32 {
+    let legendre2 = |x: f32| (3.0 * x * x - 1.0) / 2.0;
+    let s2 = -5.0 / 8.0 * legendre2(axial_tilt_deg.to_radians().cos());
+    1.0 + s2 * legendre2(lat_deg.to_radians().sin())
+}
+
+/// Distance from every point to the nearest point where `is_ocean` holds,
+/// in edges. None for points unreachable from the ocean.
+pub fn edges_to_ocean<T: Topology>(topology: &T,
+                                   is_ocean: &Vec<bool>) -> Vec<Option<uint>> {
+    let mut distance = Vec::from_elem(is_ocean.len(), None);
+    let mut to_visit = Vec::new();
+
+    for idx in range(0u, is_ocean.len()) {
+        if is_ocean[idx] {
+            distance[idx] = Some(0u);
+            to_visit.push(idx);
+        }
+    }
+
+    let mut i = 0u;
+    while i < to_visit.len() {
+        let idx = to_visit[i];
+        for &nbr in topology.neighbors(idx).iter() {
+            if distance[nbr].is_none() {
+                distance[nbr] = distance[idx].map(|d| d + 1);
+                to_visit.push(nbr);
+            }
+        }
+        i += 1;
+    }
+
+    distance
+}
+
+/// Mean annual surface temperature from insolation, elevation and
+/// distance to the ocean.
+#[deriving(Clone, Show)]
+pub struct TemperatureModel {
+    /// Sea level temperatures on the equator and the poles under the
+    /// Earth's axial tilt, in degrees Celsius. Other tilts redistribute
+    /// insolation between latitudes and temperatures follow linearly.
+    pub equator_temp_c: f32,
+    pub pole_temp_c: f32,
+    pub axial_tilt_deg: f32,
+    /// Cooling with altitude, in degrees Celsius per km.
+    pub lapse_rate_c_per_km: f32,
+    /// Elevation of a unit of world height above the sea level, in km.
+    pub km_per_height_unit: f32,
+    /// Cooling of the annual mean with distance from the ocean, as
+    /// continental winters get colder, in degrees Celsius per 1000 km.
+    pub continentality_c_per_1000_km: f32
+}
+
+impl TemperatureModel {
+    pub fn new() -> TemperatureModel {
+        TemperatureModel {
+            equator_temp_c: 27.0,
+            pole_temp_c: -25.0,
+            axial_tilt_deg: EARTH_AXIAL_TILT_DEG,
+            lapse_rate_c_per_km: 6.5,
+            km_per_height_unit: 8.0,
+            continentality_c_per_1000_km: 2.0
+        }
+    }
+
+    /// Sea level temperature at latitude `lat_deg`, in degrees Celsius.
+    pub fn sea_level_temp_c(&self, lat_deg: f32) -> f32 {
+        let equator = relative_insolation(0.0, EARTH_AXIAL_TILT_DEG);
+        let pole = relative_insolation(90.0, EARTH_AXIAL_TILT_DEG);
+        let insolation = relative_insolation(lat_deg, self.axial_tilt_deg);
+
+        self.pole_temp_c + (self.equator_temp_c - self.pole_temp_c)
+                           * (insolation - pole) / (equator - pole)
+    }
+
+    /// Temperature of every point, in degrees Celsius. Points at or below
+    /// `sea_level` are ocean surface.
+    pub fn apply<T: Topology>(&self,
+                              topology: &T,
+                              positions: &Vec<Vector3<f32>>,
+                              heights: &Vec<f32>,
+                              sea_level: f32,
+                              planet_radius_km: f32) -> Vec<f32> {
+        let is_ocean = heights.iter().map(|&h| h <= sea_level).collect();
+        let edges_to_ocean = edges_to_ocean(topology, &is_ocean);
+        let edge_km = topology.edge_length() * planet_radius_km;
+
+        range(0u, positions.len()).map(|idx| {
+            let (lat, _) = lat_lon_deg(&positions[idx]);
+            let elevation_km = (heights[idx] - sea_level).max(0.0) * self.km_per_height_unit;
+
+            // a world without ocean is continental everywhere
+            let ocean_km = match edges_to_ocean[idx] {
+                Some(edges) => edges as f32 * edge_km,
+                None => planet_radius_km
+            };
+
+            self.sea_level_temp_c(lat)
+                - elevation_km * self.lapse_rate_c_per_km
+                - ocean_km / 1000.0 * self.continentality_c_per_1000_km
+        }).collect()
+    }
+}
+
+/// Direction of the prevailing surface wind at latitude `lat_deg`, as
+/// eastward and northward components: trade winds blowing west and towards
+/// the equator below 30 degrees, westerlies blowing east and towards the
+/// pole up to 60 degrees, polar easterlies beyond.
+pub fn prevailing_wind(lat_deg: f32, meridional: f32) -> (f32, f32) {
+    let poleward = if lat_deg >= 0.0 { 1.0 } else { -1.0 };
+
+    match lat_deg.abs() {
+        0.0 ... 30.0 => (-1.0, -poleward * meridional),
+        30.0 ... 60.0 => (1.0, poleward * meridional),
+        _ => (-1.0, -poleward * meridional)
+    }
+}
+
+/// Water vapor an air column can hold at given temperature, relative to
+/// its capacity at 0 degrees Celsius. Doubles about every 11 degrees
+/// (Clausius-Clapeyron).
+fn moisture_capacity(temp_c: f32) -> f32 {
+    (0.0625 * temp_c).exp()
+}
+
+/// Annual precipitation from moisture evaporated over the ocean, carried
+/// by prevailing winds across the mesh and rained out where air rises over
+/// terrain or cools below saturation, and held back where it sinks in the
+/// subtropical highs.
+#[deriving(Clone, Show)]
+pub struct PrecipitationModel {
+    /// Strength of the equatorward or poleward component of prevailing
+    /// winds, relative to the zonal one.
+    pub wind_meridional: f32,
+    /// Fraction of moisture raining out at every step, even over flat
+    /// ground.
+    pub base_rain_fraction: f32,
+    /// Additional fraction of moisture raining out per km of ascent.
+    pub orographic_rain_per_km: f32,
+    /// Elevation of a unit of world height above the sea level, in km.
+    pub km_per_height_unit: f32,
+    /// Latitude of the subtropical highs, where air descending from the
+    /// Hadley cells warms and holds on to its moisture, in degrees.
+    pub subtropical_high_lat_deg: f32,
+    /// Half width of the dry belts around the subtropical highs, in degrees.
+    pub subtropical_width_deg: f32,
+    /// Fraction of rain suppressed at the center of the dry belts.
+    pub subtropical_drying: f32,
+    /// Precipitation averaged over the whole planet, in mm per year.
+    pub mean_precipitation_mm: f32
+}
+
+impl PrecipitationModel {
+    pub fn new() -> PrecipitationModel {
+        PrecipitationModel {
+            wind_meridional: 0.3,
+            base_rain_fraction: 0.02,
+            orographic_rain_per_km: 0.5,
+            km_per_height_unit: 8.0,
+            subtropical_high_lat_deg: 25.0,
+            subtropical_width_deg: 10.0,
+            subtropical_drying: 0.8,
+            mean_precipitation_mm: 1000.0
+        }
+    }
+
+    /// Fraction of the usual rain falling at latitude `lat_deg`, lowest
+    /// in the dry belts around the subtropical highs.
+    pub fn subsidence_factor(&self, lat_deg: f32) -> f32 {
+        let x = (lat_deg.abs() - self.subtropical_high_lat_deg) / self.subtropical_width_deg;
+        1.0 - self.subtropical_drying * (-x * x).exp()
+    }
+
+    /// Downwind neighbors of every point, with the fraction of moisture
+    /// carried to each of them.
+    fn downwind<T: Topology>(&self,
+                             topology: &T,
+                             positions: &Vec<Vector3<f32>>) -> Vec<Vec<(uint, f32)>> {
+        let north_pole = Vector3::new(0.0f32, 0.0, 1.0);
+
+        range(0u, positions.len()).map(|idx| {
+            let pos = positions[idx].normalize();
+            let east = north_pole.cross(&pos);
+            // no zonal direction at the poles: air stays in place
+            if east.length2() == 0.0 {
+                return Vec::new();
+            }
+            let east = east.normalize();
+            let north = pos.cross(&east);
+
+            let (lat, _) = lat_lon_deg(&pos);
+            let (eastward, northward) = prevailing_wind(lat, self.wind_meridional);
+            let wind = east.mul_s(eastward).add(&north.mul_s(northward));
+
+            let alignments: Vec<(uint, f32)> = topology.neighbors(idx).iter().map(|&nbr| {
+                let dir = positions[nbr].normalize().sub(&pos);
+                let tangent = dir.sub(&pos.mul_s(dir.dot(&pos))).normalize();
+                (nbr, tangent.dot(&wind).max(0.0))
+            }).filter(|&(_, alignment)| alignment > 0.0).collect();
+
+            let total = alignments.iter().fold(0.0f32, |sum, &(_, a)| sum + a);
+            alignments.iter().map(|&(nbr, a)| (nbr, a / total)).collect()
+        }).collect()
+    }
+
+    /// Precipitation at every point, in mm per year. Points at or below
+    /// `sea_level` are ocean, saturated with moisture at their
+    /// `temperature`, in degrees Celsius.
+    pub fn apply<T: Topology>(&self,
+                              topology: &T,
+                              positions: &Vec<Vector3<f32>>,
+                              heights: &Vec<f32>,
+                              sea_level: f32,
+                              temperature: &Vec<f32>) -> Vec<f32> {
+        let num_points = positions.len();
+        let downwind = self.downwind(topology, positions);
+        // enough steps for air to cross half of the planet
+        let num_steps = (PI / topology.edge_length()).ceil() as uint;
+
+        let elevation_km = |idx: uint| {
+            (heights[idx] - sea_level).max(0.0) * self.km_per_height_unit
+        };
+        let capacity: Vec<f32> = temperature.iter().map(|&t| moisture_capacity(t)).collect();
+        let subsidence: Vec<f32> = positions.iter().map(|pos| {
+            let (lat, _) = lat_lon_deg(pos);
+            self.subsidence_factor(lat)
+        }).collect();
+
+        let mut moisture = Vec::from_elem(num_points, 0.0f32);
+        let mut precipitation = Vec::from_elem(num_points, 0.0f32);
+
+        for _ in range(0u, num_steps) {
+            for idx in range(0u, num_points) {
+                if heights[idx] <= sea_level {
+                    moisture[idx] = capacity[idx];
+                }
+            }
+
+            let mut carried = Vec::from_elem(num_points, 0.0f32);
+            for idx in range(0u, num_points) {
+                if downwind[idx].is_empty() {
+                    carried[idx] += moisture[idx];
+                    continue;
+                }
+
+                for &(nbr, fraction) in downwind[idx].iter() {
+                    let amount = moisture[idx] * fraction;
+                    let ascent_km = (elevation_km(nbr) - elevation_km(idx)).max(0.0);
+                    let rain_fraction = (self.base_rain_fraction
+                                         + ascent_km * self.orographic_rain_per_km).min(1.0)
+                                        * subsidence[nbr];
+
+                    precipitation[nbr] += amount * rain_fraction;
+                    carried[nbr] += amount * (1.0 - rain_fraction);
+                }
+            }
+
+            // air cooled below saturation rains out the excess
+            for idx in range(0u, num_points) {
+                let excess = (carried[idx] - capacity[idx]).max(0.0);
+                precipitation[idx] += excess;
+                moisture[idx] = carried[idx] - excess;
+            }
+        }
+
+        let mean = precipitation.iter().fold(0.0f32, |sum, &p| sum + p) / num_points as f32;
+        if mean > 0.0 {
+            let scale = self.mean_precipitation_mm / mean;
+            for p in precipitation.iter_mut() {
+                *p *= scale;
+            }
+        }
+
+        precipitation
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::Float;
+
+    use polyhedron::make_sphere;
+    use world::World;
+    use geo::lat_lon_deg;
+
+    use super::{TemperatureModel, PrecipitationModel};
+
+    #[test]
+    fn temperature_falls_towards_the_poles() {
+        let model = TemperatureModel::new();
+
+        assert!((model.sea_level_temp_c(0.0) - model.equator_temp_c).abs() < 1.0e-3);
+        assert!((model.sea_level_temp_c(90.0) - model.pole_temp_c).abs() < 1.0e-3);
+        assert!((model.sea_level_temp_c(-45.0) - model.sea_level_temp_c(45.0)).abs() < 1.0e-3);
+        assert!(model.sea_level_temp_c(30.0) > model.sea_level_temp_c(60.0));
+    }
+
+    #[test]
+    fn mountains_cast_a_rain_shadow() {
+        let world = World::new(make_sphere(4));
+        let positions = world.positions();
+
+        // continent from 60 W to 60 E, split by a ridge along the prime
+        // meridian, in an ocean at sea level 1.0
+        let heights: Vec<f32> = positions.iter().map(|pos| {
+            let (_, lon) = lat_lon_deg(pos);
+            if lon.abs() <= 8.0 {
+                1.5
+            } else if lon.abs() <= 60.0 {
+                1.01
+            } else {
+                1.0
+            }
+        }).collect();
+        // uniform temperature: no rain from cooling air
+        let temperature = Vec::from_elem(positions.len(), 20.0f32);
+
+        let precipitation = PrecipitationModel::new().apply(&world, &positions, &heights,
+                                                            1.0, &temperature);
+
+        // trade winds blow west, so the east side of the ridge is windward
+        let mean_between = |min_lon: f32, max_lon: f32| {
+            let values: Vec<f32> = range(0u, positions.len()).filter(|&i| {
+                let (lat, lon) = lat_lon_deg(&positions[i]);
+                lat.abs() < 20.0 && lon >= min_lon && lon <= max_lon
+            }).map(|i| precipitation[i]).collect();
+            assert!(!values.is_empty());
+            values.iter().fold(0.0f32, |a, &b| a + b) / values.len() as f32
+        };
+        let windward = mean_between(15.0, 40.0);
+        let leeward = mean_between(-40.0, -15.0);
+
+        assert!(windward > 2.0 * leeward, "windward {}, leeward {}", windward, leeward);
+    }
+
+    #[test]
+    fn subtropics_are_driest() {
+        let world = World::new(make_sphere(4));
+        let positions = world.positions();
+
+        // flat continent from 60 W to 60 E and 70 S to 70 N, measured at
+        // least 30 degrees away from its coasts
+        let heights: Vec<f32> = positions.iter().map(|pos| {
+            let (lat, lon) = lat_lon_deg(pos);
+            if lat.abs() <= 70.0 && lon.abs() <= 60.0 { 1.01 } else { 1.0 }
+        }).collect();
+        let temperature = Vec::from_elem(positions.len(), 20.0f32);
+
+        let apply = |model: PrecipitationModel| {
+            model.apply(&world, &positions, &heights, 1.0, &temperature)
+        };
+        let precipitation = apply(PrecipitationModel::new());
+        let without_drying = apply(PrecipitationModel {
+            subtropical_drying: 0.0,
+            ..PrecipitationModel::new()
+        });
+
+        let mean_between = |precipitation: &Vec<f32>, min_lat: f32, max_lat: f32| {
+            let values: Vec<f32> = range(0u, positions.len()).filter(|&i| {
+                let (lat, lon) = lat_lon_deg(&positions[i]);
+                lon.abs() <= 30.0 && lat.abs() >= min_lat && lat.abs() <= max_lat
+            }).map(|i| precipitation[i]).collect();
+            assert!(!values.is_empty());
+            values.iter().fold(0.0f32, |a, &b| a + b) / values.len() as f32
+        };
+        let tropics = mean_between(&precipitation, 0.0, 15.0);
+        let subtropics = mean_between(&precipitation, 20.0, 30.0);
+        let midlatitudes = mean_between(&precipitation, 35.0, 50.0);
+
+        assert!(subtropics < tropics && subtropics < midlatitudes,
+                "tropics {}, subtropics {}, midlatitudes {}", tropics, subtropics, midlatitudes);
+        assert!(subtropics < mean_between(&without_drying, 20.0, 30.0));
+    }
+}
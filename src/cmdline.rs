@@ -5,6 +5,17 @@ use std::os;
 use std::fmt;
 use std::str::FromStr;
 
+use partition::PartitionKind;
+use plate_simulation::PlateSimParams;
+use plate_stats::StatsFormat;
+use scenario::Scenario;
+use sea_level::SeaLevel;
+use landmass::LandThresholds;
+use climate::TemperatureModel;
+use erosion::ThermalErosion;
+use world::DEFAULT_SEA_LEVEL;
+use parallel;
+
 include!("macros.rs")
 
 fn get_block(data: &[u8],
@@ -138,6 +149,34 @@ pub struct Args {
     pub plate_sim_detail_level: uint,
     pub plate_sim_steps: uint,
     pub plate_sim_plates: uint,
+    pub planet_radius_km: f32,
+    pub plate_step_myr: f32,
+    pub convection_interval: uint,
+    pub partition: PartitionKind,
+    pub plate_layout: Option<String>,
+    pub num_hotspots: uint,
+    pub hotspot_strength: f32,
+    pub export_boundaries: Option<String>,
+    pub export_plate_graph: Option<String>,
+    pub checkpoint: Option<String>,
+    pub resume: Option<String>,
+    pub num_threads: uint,
+    pub stats_format: Option<StatsFormat>,
+    pub scenario: Option<Scenario>,
+    pub export_cycle: Option<String>,
+    pub export_events: Option<String>,
+    pub export_coastlines: Option<String>,
+    pub land_thresholds: LandThresholds,
+    pub temperature_model: TemperatureModel,
+    pub biome_table: Option<String>,
+    pub export_biomes: Option<String>,
+    pub thermal_erosion_iterations: uint,
+    pub talus_angle_deg: f32,
+    pub thermal_erosion_strength: f32,
+    pub hydraulic_erosion_droplets: uint,
+    pub sea_level: SeaLevel,
+    pub river_threshold: f32,
+    pub lake_evaporation: f32,
 }
 
 impl fmt::Show for Args {
@@ -149,7 +188,35 @@ impl fmt::Show for Args {
         try!(writeln!(f, "- world_detail_level = {}", self.world_detail_level));
         try!(writeln!(f, "- plate_sim_detail_level = {}", self.plate_sim_detail_level));
         try!(writeln!(f, "- plate_sim_steps = {}", self.plate_sim_steps));
-        writeln!(f, "- plate_sim_plates = {}", self.plate_sim_plates)
+        try!(writeln!(f, "- plate_sim_plates = {}", self.plate_sim_plates));
+        try!(writeln!(f, "- planet_radius_km = {}", self.planet_radius_km));
+        try!(writeln!(f, "- plate_step_myr = {}", self.plate_step_myr));
+        try!(writeln!(f, "- convection_interval = {}", self.convection_interval));
+        try!(writeln!(f, "- partition = {}", self.partition));
+        try!(writeln!(f, "- plate_layout = {}", self.plate_layout));
+        try!(writeln!(f, "- num_hotspots = {}", self.num_hotspots));
+        try!(writeln!(f, "- hotspot_strength = {}", self.hotspot_strength));
+        try!(writeln!(f, "- export_boundaries = {}", self.export_boundaries));
+        try!(writeln!(f, "- export_plate_graph = {}", self.export_plate_graph));
+        try!(writeln!(f, "- checkpoint = {}", self.checkpoint));
+        try!(writeln!(f, "- resume = {}", self.resume));
+        try!(writeln!(f, "- num_threads = {}", self.num_threads));
+        try!(writeln!(f, "- stats_format = {}", self.stats_format));
+        try!(writeln!(f, "- scenario = {}", self.scenario));
+        try!(writeln!(f, "- export_cycle = {}", self.export_cycle));
+        try!(writeln!(f, "- export_events = {}", self.export_events));
+        try!(writeln!(f, "- export_coastlines = {}", self.export_coastlines));
+        try!(writeln!(f, "- land_thresholds = {}", self.land_thresholds));
+        try!(writeln!(f, "- temperature_model = {}", self.temperature_model));
+        try!(writeln!(f, "- biome_table = {}", self.biome_table));
+        try!(writeln!(f, "- export_biomes = {}", self.export_biomes));
+        try!(writeln!(f, "- thermal_erosion_iterations = {}", self.thermal_erosion_iterations));
+        try!(writeln!(f, "- talus_angle_deg = {}", self.talus_angle_deg));
+        try!(writeln!(f, "- thermal_erosion_strength = {}", self.thermal_erosion_strength));
+        try!(writeln!(f, "- hydraulic_erosion_droplets = {}", self.hydraulic_erosion_droplets));
+        try!(writeln!(f, "- sea_level = {}", self.sea_level));
+        try!(writeln!(f, "- river_threshold = {}", self.river_threshold));
+        writeln!(f, "- lake_evaporation = {}", self.lake_evaporation)
     }
 }
 
@@ -195,6 +262,38 @@ impl Args {
              optopt("p", "plate-detail", "plate simulation detail level",    "NUM"),
              optopt("P", "plate-steps",  "number of plate simulation steps", "NUM"),
              optopt("n", "plate-count",  "number of plates to generate",     "NUM"),
+             optopt("R", "planet-radius", "planet radius in km",             "NUM"),
+             optopt("t", "step-myr",     "plate simulation step length in Myr", "NUM"),
+             optopt("c", "convection",   "drive plates by mantle convection every NUM steps (0 = off)", "NUM"),
+             optopt("", "partition",     "plate partition strategy: flood, voronoi, weighted or noisy", "NAME"),
+             optopt("", "plate-layout",  "load plates from a JSON layout instead of partitioning", "FILE"),
+             optopt("H", "hotspots",     "number of mantle plume hotspots",  "NUM"),
+             optopt("", "hotspot-strength", "hotspot uplift per Myr",        "NUM"),
+             optopt("", "export-boundaries", "write final plate boundaries as OBJ lines", "FILE"),
+             optopt("", "export-plate-graph", "write final plate adjacency graph in DOT format", "FILE"),
+             optopt("", "checkpoint",    "save plate simulation state after the last step", "FILE"),
+             optopt("", "resume",        "continue plate simulation from a checkpoint up to plate-steps", "FILE"),
+             optopt("j", "threads",      "number of worker threads",         "NUM"),
+             optopt("", "stats",         "print per-step plate statistics as text or json", "FORMAT"),
+             optopt("", "scenario",      "run a preset scenario: supercontinent", "NAME"),
+             optopt("", "export-cycle",  "write supercontinent cycle metrics as CSV", "FILE"),
+             optopt("", "export-events", "write earthquakes, eruptions, rifts and sutures as CSV", "FILE"),
+             optopt("", "export-coastlines", "write coastline points with coastline lengths and landmass areas as CSV", "FILE"),
+             optopt("", "continent-area", "smallest landmass counted as a continent, in km^2", "NUM"),
+             optopt("", "island-area",   "smallest landmass counted as an island, in km^2", "NUM"),
+             optopt("", "equator-temp",  "mean sea level temperature on the equator, in degrees C", "NUM"),
+             optopt("", "pole-temp",     "mean sea level temperature on the poles, in degrees C", "NUM"),
+             optopt("", "axial-tilt",    "axial tilt of the planet, in degrees", "NUM"),
+             optopt("", "biome-table",   "load biome lookup table from a JSON file", "FILE"),
+             optopt("", "export-biomes", "write biome of every world vertex as CSV", "FILE"),
+             optopt("", "thermal-erosion", "number of thermal erosion iterations (0 = off)", "NUM"),
+             optopt("", "talus-angle",   "steepest slope left by thermal erosion, in degrees", "NUM"),
+             optopt("", "thermal-strength", "fraction of unstable material moved per iteration", "NUM"),
+             optopt("", "hydraulic-erosion", "number of hydraulic erosion droplets (0 = off)", "NUM"),
+             optopt("", "sea-level",     "height of the sea surface, relative to planet radius", "NUM"),
+             optopt("", "land-fraction", "choose the sea level leaving this fraction of the surface dry", "NUM"),
+             optopt("", "river-threshold", "number of vertices a river drains at its source (0 = no drainage)", "NUM"),
+             optopt("", "lake-evaporation", "evaporation per lake vertex; lakes with smaller inflow turn salt", "NUM"),
             optflag("h", "help",         "print this message and exit"),
         ];
 
@@ -211,6 +310,8 @@ impl Args {
             return Err(0);
         }
 
+        let plate_sim_defaults = PlateSimParams::new(25);
+        let thermal_erosion_defaults = ThermalErosion::new(0);
         let mut ret: Args = Args {
             rng_seed: String::from_str("asd"),
             rng_seed_hash: [1, 2, 3, 4],
@@ -218,7 +319,35 @@ impl Args {
             world_detail_level: 4,
             plate_sim_detail_level: 2,
             plate_sim_steps: 10,
-            plate_sim_plates: 25,
+            plate_sim_plates: plate_sim_defaults.num_plates,
+            planet_radius_km: plate_sim_defaults.planet_radius_km,
+            plate_step_myr: plate_sim_defaults.step_myr,
+            convection_interval: 0,
+            partition: PartitionKind::FloodFill,
+            plate_layout: None,
+            num_hotspots: 0,
+            hotspot_strength: plate_sim_defaults.hotspot_strength,
+            export_boundaries: None,
+            export_plate_graph: None,
+            checkpoint: None,
+            resume: None,
+            num_threads: parallel::default_num_threads(),
+            stats_format: None,
+            scenario: None,
+            export_cycle: None,
+            export_events: None,
+            export_coastlines: None,
+            land_thresholds: LandThresholds::new(),
+            temperature_model: TemperatureModel::new(),
+            biome_table: None,
+            export_biomes: None,
+            thermal_erosion_iterations: thermal_erosion_defaults.iterations,
+            talus_angle_deg: thermal_erosion_defaults.talus_angle_deg,
+            thermal_erosion_strength: thermal_erosion_defaults.strength,
+            hydraulic_erosion_droplets: 0,
+            sea_level: SeaLevel::Absolute(DEFAULT_SEA_LEVEL),
+            river_threshold: 0.0,
+            lake_evaporation: 2.0,
         };
 
         match matches.opt_str("s") {
@@ -249,6 +378,124 @@ impl Args {
             Some(arg) => ret.plate_sim_plates = from_str_or_panic(arg.as_slice()),
             None => {}
         }
+        match matches.opt_str("R") {
+            Some(arg) => ret.planet_radius_km = from_str_or_panic(arg.as_slice()),
+            None => {}
+        }
+        match matches.opt_str("t") {
+            Some(arg) => ret.plate_step_myr = from_str_or_panic(arg.as_slice()),
+            None => {}
+        }
+        match matches.opt_str("c") {
+            Some(arg) => ret.convection_interval = from_str_or_panic(arg.as_slice()),
+            None => {}
+        }
+        match matches.opt_str("partition") {
+            Some(arg) => ret.partition = match PartitionKind::from_name(arg.as_slice()) {
+                Some(kind) => kind,
+                None => {
+                    panic_bt!("invalid partition strategy: {}, use -h for help", arg);
+                }
+            },
+            None => {}
+        }
+        ret.plate_layout = matches.opt_str("plate-layout");
+        match matches.opt_str("H") {
+            Some(arg) => ret.num_hotspots = from_str_or_panic(arg.as_slice()),
+            None => {}
+        }
+        match matches.opt_str("hotspot-strength") {
+            Some(arg) => ret.hotspot_strength = from_str_or_panic(arg.as_slice()),
+            None => {}
+        }
+        ret.export_boundaries = matches.opt_str("export-boundaries");
+        ret.export_plate_graph = matches.opt_str("export-plate-graph");
+        ret.checkpoint = matches.opt_str("checkpoint");
+        ret.resume = matches.opt_str("resume");
+
+        match matches.opt_str("j") {
+            Some(arg) => ret.num_threads = from_str_or_panic(arg.as_slice()),
+            None => {}
+        }
+        match matches.opt_str("stats") {
+            Some(arg) => ret.stats_format = match StatsFormat::from_name(arg.as_slice()) {
+                Some(format) => Some(format),
+                None => {
+                    panic_bt!("invalid statistics format: {}, use -h for help", arg);
+                }
+            },
+            None => {}
+        }
+        match matches.opt_str("scenario") {
+            Some(arg) => ret.scenario = match Scenario::from_name(arg.as_slice()) {
+                Some(scenario) => Some(scenario),
+                None => {
+                    panic_bt!("invalid scenario: {}, use -h for help", arg);
+                }
+            },
+            None => {}
+        }
+        // cycle state is not stored in checkpoints
+        if ret.scenario.is_some() && ret.resume.is_some() {
+            panic_bt!("--scenario cannot be combined with --resume, use -h for help");
+        }
+        ret.export_cycle = matches.opt_str("export-cycle");
+        ret.export_events = matches.opt_str("export-events");
+        ret.export_coastlines = matches.opt_str("export-coastlines");
+        ret.biome_table = matches.opt_str("biome-table");
+        ret.export_biomes = matches.opt_str("export-biomes");
+        match matches.opt_str("continent-area") {
+            Some(arg) => ret.land_thresholds.continent_km2 = from_str_or_panic(arg.as_slice()),
+            None => {}
+        }
+        match matches.opt_str("island-area") {
+            Some(arg) => ret.land_thresholds.island_km2 = from_str_or_panic(arg.as_slice()),
+            None => {}
+        }
+        match matches.opt_str("equator-temp") {
+            Some(arg) => ret.temperature_model.equator_temp_c = from_str_or_panic(arg.as_slice()),
+            None => {}
+        }
+        match matches.opt_str("pole-temp") {
+            Some(arg) => ret.temperature_model.pole_temp_c = from_str_or_panic(arg.as_slice()),
+            None => {}
+        }
+        match matches.opt_str("axial-tilt") {
+            Some(arg) => ret.temperature_model.axial_tilt_deg = from_str_or_panic(arg.as_slice()),
+            None => {}
+        }
+        match matches.opt_str("thermal-erosion") {
+            Some(arg) => ret.thermal_erosion_iterations = from_str_or_panic(arg.as_slice()),
+            None => {}
+        }
+        match matches.opt_str("talus-angle") {
+            Some(arg) => ret.talus_angle_deg = from_str_or_panic(arg.as_slice()),
+            None => {}
+        }
+        match matches.opt_str("thermal-strength") {
+            Some(arg) => ret.thermal_erosion_strength = from_str_or_panic(arg.as_slice()),
+            None => {}
+        }
+        match matches.opt_str("hydraulic-erosion") {
+            Some(arg) => ret.hydraulic_erosion_droplets = from_str_or_panic(arg.as_slice()),
+            None => {}
+        }
+        match (matches.opt_str("sea-level"), matches.opt_str("land-fraction")) {
+            (Some(_), Some(_)) => {
+                panic_bt!("--sea-level and --land-fraction are mutually exclusive, use -h for help");
+            },
+            (Some(arg), None) => ret.sea_level = SeaLevel::Absolute(from_str_or_panic(arg.as_slice())),
+            (None, Some(arg)) => ret.sea_level = SeaLevel::LandFraction(from_str_or_panic(arg.as_slice())),
+            (None, None) => {}
+        }
+        match matches.opt_str("river-threshold") {
+            Some(arg) => ret.river_threshold = from_str_or_panic(arg.as_slice()),
+            None => {}
+        }
+        match matches.opt_str("lake-evaporation") {
+            Some(arg) => ret.lake_evaporation = from_str_or_panic(arg.as_slice()),
+            None => {}
+        }
 
         Ok(ret)
     }
@@ -0,0 +1,209 @@
+extern crate cgmath;
+
+use std::vec::Vec;
+use std::io::IoResult;
+
+use cgmath::{EuclideanVector, Vector, Vector3};
+
+use polyhedron::Polyhedron;
+use geo::{angle_between, lat_lon_deg, triangle_area};
+
+/// Closed line where the land meets the sea.
+#[deriving(Clone, Show)]
+pub struct Coastline {
+    /// Landmass on the inner side of the coastline.
+    pub landmass: uint,
+    /// Water body on the outer side of the coastline.
+    pub water_body: uint,
+    /// Points on the unit sphere, ordered so that land lies to the left
+    /// when seen from above. The last point connects back to the first.
+    pub points: Vec<Vector3<f32>>,
+    pub length_km: f32
+}
+
+/// Coastlines of a world and the landmasses they bound.
+pub struct Coastlines {
+    pub coastlines: Vec<Coastline>,
+    /// Landmass of every land vertex.
+    pub landmass_of: Vec<Option<uint>>,
+    /// Area of every landmass, including the land part of faces crossed
+    /// by the coastline.
+    pub landmass_areas_km2: Vec<f32>
+}
+
+/// Point where sea level crosses the edge between vertices `a` and `b`,
+/// interpolated linearly between their heights.
+fn crossing_point(poly: &Polyhedron,
+                  heights: &Vec<f32>,
+                  sea_level: f32,
+                  a: uint,
+                  b: uint) -> Vector3<f32> {
+    let t = (sea_level - heights[a]) / (heights[b] - heights[a]);
+    let pa = poly.vertices[a].pos.normalize();
+    let pb = poly.vertices[b].pos.normalize();
+    pa.add(&pb.sub(&pa).mul_s(t)).normalize()
+}
+
+impl Coastlines {
+    /// Traces coastlines at `sea_level` across faces of `poly` with both
+    /// land and sea vertices (marching triangles). `landmass_of` gives the
+    /// landmass of every vertex above sea level, `water_body_of` the water
+    /// body of every other vertex.
+    pub fn extract(poly: &Polyhedron,
+                   heights: &Vec<f32>,
+                   sea_level: f32,
+                   landmass_of: &Vec<Option<uint>>,
+                   water_body_of: &Vec<Option<uint>>,
+                   num_landmasses: uint,
+                   planet_radius_km: f32) -> Coastlines {
+        let is_land = |idx: uint| heights[idx] > sea_level;
+
+        let crossings: Vec<Option<Vector3<f32>>> = poly.edges.iter().map(|edge| {
+            let (a, b) = (edge.vertex_indices[0], edge.vertex_indices[1]);
+            if is_land(a) != is_land(b) {
+                Some(crossing_point(poly, heights, sea_level, a, b))
+            } else {
+                None
+            }
+        }).collect();
+
+        let radius_sq = planet_radius_km * planet_radius_km;
+        let mut landmass_areas_km2 = Vec::from_elem(num_landmasses, 0.0f32);
+        // coastline segment leaving every crossed edge
+        let mut next_edge = Vec::from_elem(poly.edges.len(), None);
+
+        for face in poly.faces.iter() {
+            let verts = face.vertex_indices;
+            let landmass = match verts.iter().filter_map(|&idx| landmass_of[idx]).next() {
+                Some(landmass) => landmass,
+                None => continue
+            };
+
+            // land part of the face, clipped at the crossings
+            let mut land_poly = Vec::with_capacity(4);
+            for i in range(0u, 3) {
+                let (a, b) = (verts[i], verts[(i + 1) % 3]);
+                if is_land(a) {
+                    land_poly.push(poly.vertices[a].pos.normalize());
+                }
+                if is_land(a) != is_land(b) {
+                    land_poly.push(crossing_point(poly, heights, sea_level, a, b));
+                }
+            }
+            for i in range(1u, land_poly.len() - 1) {
+                landmass_areas_km2[landmass] += triangle_area(&land_poly[0], &land_poly[i],
+                                                              &land_poly[i + 1]) * radius_sq;
+            }
+
+            let crossed: Vec<uint> = face.edge_indices.iter()
+                                                      .map(|&e| e)
+                                                      .filter(|&e| crossings[e].is_some())
+                                                      .collect();
+            if crossed.len() != 2 {
+                continue;
+            }
+
+            let p = crossings[crossed[0]].unwrap();
+            let q = crossings[crossed[1]].unwrap();
+            let land_verts: Vec<Vector3<f32>> = verts.iter()
+                                                     .filter(|&&idx| is_land(idx))
+                                                     .map(|&idx| poly.vertices[idx].pos.normalize())
+                                                     .collect();
+            let land_center = land_verts.iter()
+                                        .fold(Vector3::new(0.0f32, 0.0, 0.0), |sum, v| sum.add(v))
+                                        .div_s(land_verts.len() as f32);
+
+            // land to the left of p -> q, looking down at the surface
+            if q.sub(&p).cross(&land_center.sub(&p)).dot(&p) >= 0.0 {
+                next_edge[crossed[0]] = Some(crossed[1]);
+            } else {
+                next_edge[crossed[1]] = Some(crossed[0]);
+            }
+        }
+
+        let mut traced = Vec::from_elem(poly.edges.len(), false);
+        let mut coastlines = Vec::new();
+
+        for start in range(0u, poly.edges.len()) {
+            if traced[start] || next_edge[start].is_none() {
+                continue;
+            }
+
+            let edge = &poly.edges[start];
+            let (land, sea) = if is_land(edge.vertex_indices[0]) {
+                (edge.vertex_indices[0], edge.vertex_indices[1])
+            } else {
+                (edge.vertex_indices[1], edge.vertex_indices[0])
+            };
+            let landmass = landmass_of[land].unwrap();
+            let water_body = water_body_of[sea].unwrap();
+
+            let mut points = Vec::new();
+            let mut edge_idx = start;
+            loop {
+                traced[edge_idx] = true;
+                points.push(crossings[edge_idx].unwrap());
+
+                edge_idx = match next_edge[edge_idx] {
+                    Some(next) if !traced[next] => next,
+                    _ => break
+                };
+            }
+
+            let length_km = range(0u, points.len()).fold(0.0f32, |sum, i| {
+                sum + angle_between(&points[i], &points[(i + 1) % points.len()])
+            }) * planet_radius_km;
+
+            coastlines.push(Coastline {
+                landmass: landmass,
+                water_body: water_body,
+                points: points,
+                length_km: length_km
+            });
+        }
+
+        Coastlines {
+            coastlines: coastlines,
+            landmass_of: landmass_of.clone(),
+            landmass_areas_km2: landmass_areas_km2
+        }
+    }
+
+    /// Total length of the coastlines of `landmass`, including those of
+    /// lakes and inland seas inside it.
+    pub fn landmass_coastline_km(&self, landmass: uint) -> f32 {
+        self.coastlines.iter()
+                       .filter(|c| c.landmass == landmass)
+                       .fold(0.0f32, |sum, c| sum + c.length_km)
+    }
+
+    /// Total length of the coastlines of `water_body`, including those of
+    /// islands inside it.
+    pub fn water_body_coastline_km(&self, water_body: uint) -> f32 {
+        self.coastlines.iter()
+                       .filter(|c| c.water_body == water_body)
+                       .fold(0.0f32, |sum, c| sum + c.length_km)
+    }
+
+    pub fn print_summary(&self) {
+        let total_km = self.coastlines.iter().fold(0.0f32, |sum, c| sum + c.length_km);
+        println!("{} coastlines around {} landmasses, {} km in total",
+                 self.coastlines.len(), self.landmass_areas_km2.len(), total_km);
+    }
+
+    /// Writes every coastline point with its coastline and landmass index,
+    /// the length of its coastline and the area of its landmass.
+    pub fn write_csv<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        try!(writeln!(w, "coastline,landmass,length_km,landmass_area_km2,lat,lon"));
+        for (coastline_idx, coastline) in self.coastlines.iter().enumerate() {
+            let area = self.landmass_areas_km2[coastline.landmass];
+            for point in coastline.points.iter() {
+                let (lat, lon) = lat_lon_deg(point);
+                try!(writeln!(w, "{},{},{},{},{},{}",
+                              coastline_idx, coastline.landmass, coastline.length_km,
+                              area, lat, lon));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+extern crate cgmath;
+
+use std::vec::Vec;
+use std::collections::TreeMap;
+
+use cgmath::{EuclideanVector, Vector, Vector3};
+
+use plate_simulation::{BoundaryType, classify_boundary};
+
+/// Number of consecutive steps two continental plates need to collide
+/// head-on before they are welded into a single plate.
+pub const SUTURE_STEPS: uint = 3;
+
+/// Minimum ratio of closing speed to relative speed along a boundary for
+/// a collision to count as head-on rather than oblique.
+pub const HEAD_ON_RATIO: f32 = 0.7;
+
+/// Points plates are made of, connected by edges. Hides the shape of the
+/// surface, so that plate logic does not depend on the unit sphere.
+pub trait Topology {
+    fn num_points(&self) -> uint;
+
+    fn neighbors(&self, idx: uint) -> &[uint];
+
+    /// Distance between two points, measured in the embedding space of the
+    /// surface.
+    fn distance(&self, a: uint, b: uint) -> f32;
+
+    /// Nominal distance between neighboring points.
+    fn edge_length(&self) -> f32;
+
+    fn num_edges(&self) -> uint;
+
+    /// Point indices of an edge.
+    fn edge(&self, edge_idx: uint) -> [uint, ..2];
+}
+
+/// Topology with every point carried by a moving plate.
+pub trait PlateDomain: Topology {
+    fn plate_of(&self, idx: uint) -> uint;
+
+    /// Motion of point `a` relative to point `b`, as closing speed (speed
+    /// of approach, negative if the points move apart) and total relative
+    /// speed. None if the direction between the points is undefined.
+    fn relative_motion(&self, a: uint, b: uint) -> Option<(f32, f32)>;
+}
+
+/// Points of the unit sphere connected by mesh edges.
+pub struct SphereMesh {
+    pub positions: Vec<Vector3<f32>>,
+    pub neighbors: Vec<Vec<uint>>,
+    pub edges: Vec<[uint, ..2]>
+}
+
+impl Topology for SphereMesh {
+    fn num_points(&self) -> uint {
+        self.positions.len()
+    }
+
+    fn neighbors(&self, idx: uint) -> &[uint] {
+        self.neighbors[idx].as_slice()
+    }
+
+    fn distance(&self, a: uint, b: uint) -> f32 {
+        self.positions[a].sub(&self.positions[b]).length()
+    }
+
+    fn edge_length(&self) -> f32 {
+        let edge = &self.edges[0];
+        self.distance(edge[0], edge[1])
+    }
+
+    fn num_edges(&self) -> uint {
+        self.edges.len()
+    }
+
+    fn edge(&self, edge_idx: uint) -> [uint, ..2] {
+        self.edges[edge_idx]
+    }
+}
+
+/// Mesh edge connecting points of two different plates.
+#[deriving(Clone, Show)]
+pub struct BoundaryEdge {
+    pub edge_idx: uint,
+    /// Plates on either side, lower index first.
+    pub plate_indices: [uint, ..2],
+    /// Points on either side, in the same order as `plate_indices`.
+    pub vertex_indices: [uint, ..2],
+    pub boundary_type: BoundaryType,
+    pub closing_speed: f32,
+    pub relative_speed: f32
+}
+
+/// Walks the edges of `domain` and returns every edge that crosses a
+/// plate boundary, in edge order.
+pub fn boundary_edges<D: PlateDomain>(domain: &D) -> Vec<BoundaryEdge> {
+    let mut boundary = Vec::new();
+
+    for edge_idx in range(0u, domain.num_edges()) {
+        let edge = domain.edge(edge_idx);
+        let (a, b) = if domain.plate_of(edge[0]) < domain.plate_of(edge[1]) {
+            (edge[0], edge[1])
+        } else {
+            (edge[1], edge[0])
+        };
+
+        let plates = [domain.plate_of(a), domain.plate_of(b)];
+        if plates[0] == plates[1] {
+            continue;
+        }
+
+        match domain.relative_motion(a, b) {
+            Some((closing_speed, relative_speed)) => boundary.push(BoundaryEdge {
+                edge_idx: edge_idx,
+                plate_indices: plates,
+                vertex_indices: [a, b],
+                boundary_type: classify_boundary(closing_speed, relative_speed),
+                closing_speed: closing_speed,
+                relative_speed: relative_speed
+            }),
+            None => {}
+        }
+    }
+
+    boundary
+}
+
+/// Returns pairs of plates (lower index first) whose common boundary is,
+/// on average, closing head-on.
+pub fn head_on_collisions(boundary: &[BoundaryEdge]) -> Vec<(uint, uint)> {
+    let mut closing: TreeMap<(uint, uint), (f32, f32)> = TreeMap::new();
+
+    for edge in boundary.iter() {
+        let key = (edge.plate_indices[0], edge.plate_indices[1]);
+        let (closing_sum, rel_sum) = match closing.get(&key) {
+            Some(&sums) => sums,
+            None => (0.0f32, 0.0f32)
+        };
+        closing.insert(key, (closing_sum + edge.closing_speed,
+                             rel_sum + edge.relative_speed));
+    }
+
+    closing.iter()
+           .filter(|&(_, &(closing_sum, rel_sum))|
+                   closing_sum > 0.0 && closing_sum >= rel_sum * HEAD_ON_RATIO)
+           .map(|(&key, _)| key)
+           .collect()
+}
+
+/// Counts consecutive steps of head-on collision between pairs of plates
+/// for which `can_suture` holds. Returns updated counts and the first
+/// pair that collided for `SUTURE_STEPS` steps, if any.
+pub fn track_collisions(collision_steps: &TreeMap<(uint, uint), uint>,
+                        head_on: &[(uint, uint)],
+                        can_suture: |uint, uint| -> bool)
+        -> (TreeMap<(uint, uint), uint>, Option<(uint, uint)>) {
+    let mut new_steps = TreeMap::new();
+    let mut to_merge = None;
+
+    for &(a, b) in head_on.iter() {
+        if !can_suture(a, b) {
+            continue;
+        }
+
+        let steps = 1 + match collision_steps.get(&(a, b)) {
+            Some(&steps) => steps,
+            None => 0
+        };
+        new_steps.insert((a, b), steps);
+
+        if steps >= SUTURE_STEPS && to_merge.is_none() {
+            to_merge = Some((a, b));
+        }
+    }
+
+    (new_steps, to_merge)
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::Float;
+    use std::collections::TreeMap;
+
+    use plate_simulation::BoundaryType;
+    use torus::TorusGrid;
+
+    use super::{boundary_edges, head_on_collisions, track_collisions};
+
+    /// 6x3 grid split into three vertical bands, two columns each: plate 0
+    /// moves right, plate 1 left, plate 2 right and up.
+    fn three_bands() -> TorusGrid {
+        let mut grid = TorusGrid::new(6, 3);
+        grid.fill_plate(2, 0, 2, 3, 1);
+        grid.fill_plate(4, 0, 2, 3, 2);
+        grid.plate_velocities = vec![(1.0, 0.0), (-1.0, 0.0), (2.0, 3.0)];
+        grid
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1.0e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn boundary_edges_of_bands() {
+        let grid = three_bands();
+        let boundary = boundary_edges(&grid);
+
+        // horizontal edge from (x, y) has index 2 * (6 * y + x)
+        let edge_indices: Vec<uint> = boundary.iter().map(|e| e.edge_idx).collect();
+        assert_eq!(edge_indices, vec![2u, 6, 10, 14, 18, 22, 26, 30, 34]);
+
+        // bands 0 and 1 meet head-on between columns 1 and 2
+        let e = &boundary[0];
+        assert_eq!(e.plate_indices.to_vec(), vec![0u, 1]);
+        assert_eq!(e.vertex_indices.to_vec(), vec![1u, 2]);
+        assert_close(e.closing_speed, 2.0);
+        assert_close(e.relative_speed, 2.0);
+        assert_eq!(e.boundary_type, BoundaryType::Convergent);
+
+        // bands 1 and 2 separate between columns 3 and 4: relative motion
+        // (-3, -3) along +x
+        let e = &boundary[1];
+        assert_eq!(e.plate_indices.to_vec(), vec![1u, 2]);
+        assert_eq!(e.vertex_indices.to_vec(), vec![3u, 4]);
+        assert_close(e.closing_speed, -3.0);
+        assert_close(e.relative_speed, 18.0f32.sqrt());
+        assert_eq!(e.boundary_type, BoundaryType::Divergent);
+
+        // bands 2 and 0 meet across the wrapped edge: relative motion
+        // (-1, -3) along -x, mostly sideways
+        let e = &boundary[2];
+        assert_eq!(e.plate_indices.to_vec(), vec![0u, 2]);
+        assert_eq!(e.vertex_indices.to_vec(), vec![0u, 5]);
+        assert_close(e.closing_speed, 1.0);
+        assert_close(e.relative_speed, 10.0f32.sqrt());
+        assert_eq!(e.boundary_type, BoundaryType::Transform);
+    }
+
+    #[test]
+    fn only_head_on_boundaries_collide() {
+        let grid = three_bands();
+        let boundary = boundary_edges(&grid);
+
+        // plates 0 and 2 also close in, but at 1 / sqrt(10) of their
+        // relative speed
+        assert_eq!(head_on_collisions(boundary.as_slice()), vec![(0u, 1u)]);
+    }
+
+    #[test]
+    fn collisions_are_counted_until_suture() {
+        let mut steps = TreeMap::new();
+        steps.insert((0u, 1u), 1u);
+        steps.insert((0u, 2u), 1u);
+        let head_on = [(0u, 1u), (1u, 2u)];
+
+        // (0, 2) stopped colliding, (1, 2) cannot suture
+        let (steps, to_merge) = track_collisions(&steps, head_on.as_slice(),
+                                                 |a, b| (a, b) != (1, 2));
+        assert_eq!(steps.iter().map(|(&k, &v)| (k, v)).collect::<Vec<((uint, uint), uint)>>(),
+                   vec![((0u, 1u), 2u)]);
+        assert_eq!(to_merge, None);
+
+        let (steps, to_merge) = track_collisions(&steps, head_on.as_slice(), |_, _| true);
+        assert_eq!(steps.iter().map(|(&k, &v)| (k, v)).collect::<Vec<((uint, uint), uint)>>(),
+                   vec![((0u, 1u), 3u), ((1u, 2u), 1u)]);
+        assert_eq!(to_merge, Some((0u, 1u)));
+    }
+}
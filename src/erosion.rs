@@ -0,0 +1,184 @@
+use std::vec::Vec;
+use std::num::{Float, FloatMath};
+use std::rand::Rng;
+
+use domain::Topology;
+
+/// Thermal weathering: material on slopes steeper than the talus angle
+/// crumbles and slides down to lower neighbors.
+pub struct ThermalErosion {
+    /// Steepest stable slope, in degrees.
+    pub talus_angle_deg: f32,
+    pub iterations: uint,
+    /// Fraction of the unstable material moved in each iteration, in (0, 1].
+    pub strength: f32
+}
+
+impl ThermalErosion {
+    pub fn new(iterations: uint) -> ThermalErosion {
+        ThermalErosion {
+            talus_angle_deg: 30.0,
+            iterations: iterations,
+            strength: 0.5
+        }
+    }
+
+    /// Erodes `heights` of points of `topology`. All points are updated at
+    /// once in every iteration, so the result does not depend on the order
+    /// points are visited in. Returns the total height moved.
+    pub fn apply<T: Topology>(&self,
+                              topology: &T,
+                              heights: &mut Vec<f32>) -> f32 {
+        let max_slope = self.talus_angle_deg.to_radians().tan();
+        let mut total_moved = 0.0f32;
+
+        for _ in range(0u, self.iterations) {
+            let mut deltas = Vec::from_elem(heights.len(), 0.0f32);
+
+            for idx in range(0u, heights.len()) {
+                let nbrs = topology.neighbors(idx);
+                let excess: Vec<f32> = nbrs.iter().map(|&nbr| {
+                    let max_drop = max_slope * topology.distance(idx, nbr);
+                    (heights[idx] - heights[nbr] - max_drop).max(0.0)
+                }).collect();
+
+                let excess_sum = excess.iter().fold(0.0f32, |a, &b| a + b);
+                if excess_sum <= 0.0 {
+                    continue;
+                }
+
+                // moving half of the largest excess levels the steepest slope
+                let max_excess = excess.iter().fold(0.0f32, |a, &b| a.max(b));
+                let amount = self.strength * max_excess / 2.0;
+
+                deltas[idx] -= amount;
+                for i in range(0u, nbrs.len()) {
+                    deltas[nbrs[i]] += amount * excess[i] / excess_sum;
+                }
+                total_moved += amount;
+            }
+
+            for idx in range(0u, heights.len()) {
+                heights[idx] += deltas[idx];
+            }
+        }
+
+        total_moved
+    }
+}
+
+/// Material moved by hydraulic erosion.
+#[deriving(Clone, Show)]
+pub struct ErosionReport {
+    /// Total height removed from the surface.
+    pub eroded: f32,
+    /// Total height deposited back. Equal to `eroded`, up to rounding,
+    /// since droplets drop all sediment they still carry when they stop.
+    pub deposited: f32
+}
+
+/// Particle-based hydraulic erosion: droplets of water fall on random
+/// points and flow down the steepest mesh edge, picking up sediment while
+/// they can carry more and dropping it when they slow down, fall into a
+/// pit or evaporate.
+pub struct HydraulicErosion {
+    pub num_droplets: uint,
+    /// Maximum number of edges a droplet flows along.
+    pub max_steps: uint,
+    /// Sediment a unit of water can carry down a unit slope.
+    pub capacity: f32,
+    /// Fraction of the free capacity filled by erosion in each step.
+    pub erosion_rate: f32,
+    /// Fraction of the excess sediment deposited in each step.
+    pub deposition_rate: f32,
+    /// Fraction of water evaporating in each step.
+    pub evaporation: f32,
+    /// Slope used for capacity on nearly flat ground, so that droplets
+    /// crossing plains still carry some sediment.
+    pub min_slope: f32
+}
+
+impl HydraulicErosion {
+    pub fn new(num_droplets: uint) -> HydraulicErosion {
+        HydraulicErosion {
+            num_droplets: num_droplets,
+            max_steps: 64,
+            capacity: 0.01,
+            erosion_rate: 0.3,
+            deposition_rate: 0.3,
+            evaporation: 0.02,
+            min_slope: 0.01
+        }
+    }
+
+    /// Lowest neighbor of `idx` and the slope towards it, if any neighbor
+    /// is lower than `idx`.
+    fn steepest_descent<T: Topology>(topology: &T,
+                                     heights: &Vec<f32>,
+                                     idx: uint) -> Option<(uint, f32)> {
+        let mut best = None;
+        let mut best_slope = 0.0f32;
+
+        for &nbr in topology.neighbors(idx).iter() {
+            let slope = (heights[idx] - heights[nbr]) / topology.distance(idx, nbr);
+            if slope > best_slope {
+                best_slope = slope;
+                best = Some(nbr);
+            }
+        }
+
+        best.map(|nbr| (nbr, best_slope))
+    }
+
+    /// Erodes `heights` of points of `topology`, with droplet start points
+    /// drawn from `rng`.
+    pub fn apply<T: Topology, R: Rng>(&self,
+                                      topology: &T,
+                                      heights: &mut Vec<f32>,
+                                      rng: &mut R) -> ErosionReport {
+        // droplets with less water than this dry up
+        const MIN_WATER: f32 = 0.01;
+
+        let mut report = ErosionReport { eroded: 0.0, deposited: 0.0 };
+
+        for _ in range(0u, self.num_droplets) {
+            let mut idx = rng.gen_range(0u, heights.len());
+            let mut water = 1.0f32;
+            let mut sediment = 0.0f32;
+
+            for _ in range(0u, self.max_steps) {
+                let (next, slope) = match HydraulicErosion::steepest_descent(topology, heights, idx) {
+                    Some(descent) => descent,
+                    // pit: the droplet stops here
+                    None => break
+                };
+
+                let capacity = slope.max(self.min_slope) * water * self.capacity;
+                if sediment > capacity {
+                    let amount = (sediment - capacity) * self.deposition_rate;
+                    sediment -= amount;
+                    heights[idx] += amount;
+                    report.deposited += amount;
+                } else {
+                    // never dig below the point the droplet flows to
+                    let drop = heights[idx] - heights[next];
+                    let amount = ((capacity - sediment) * self.erosion_rate).min(drop);
+                    sediment += amount;
+                    heights[idx] -= amount;
+                    report.eroded += amount;
+                }
+
+                idx = next;
+                water *= 1.0 - self.evaporation;
+                if water < MIN_WATER {
+                    break;
+                }
+            }
+
+            heights[idx] += sediment;
+            report.deposited += sediment;
+        }
+
+        report
+    }
+}
@@ -0,0 +1,333 @@
+extern crate cgmath;
+
+use std::vec::Vec;
+use std::num::Float;
+use std::collections::TreeMap;
+use std::io::IoResult;
+
+use cgmath::Vector3;
+
+use geo::{angle_between, lat_lon_deg};
+use plate_simulation::{BoundarySegment, BoundaryType};
+use checkpoint::{invalid_data, write_uint, read_uint, write_bool, read_bool,
+                 write_vec3, read_vec3};
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum EventKind {
+    Earthquake,
+    Eruption,
+    Rift,
+    Suture
+}
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            EventKind::Earthquake => "earthquake",
+            EventKind::Eruption => "eruption",
+            EventKind::Rift => "rift",
+            EventKind::Suture => "suture"
+        }
+    }
+
+    fn to_idx(&self) -> uint {
+        match *self {
+            EventKind::Earthquake => 0,
+            EventKind::Eruption => 1,
+            EventKind::Rift => 2,
+            EventKind::Suture => 3
+        }
+    }
+
+    fn from_idx(idx: uint) -> Option<EventKind> {
+        match idx {
+            0 => Some(EventKind::Earthquake),
+            1 => Some(EventKind::Eruption),
+            2 => Some(EventKind::Rift),
+            3 => Some(EventKind::Suture),
+            _ => None
+        }
+    }
+}
+
+fn boundary_type_to_idx(boundary_type: &BoundaryType) -> uint {
+    match *boundary_type {
+        BoundaryType::Convergent => 0,
+        BoundaryType::Divergent => 1,
+        BoundaryType::Transform => 2
+    }
+}
+
+fn boundary_type_from_idx(idx: uint) -> Option<BoundaryType> {
+    match idx {
+        0 => Some(BoundaryType::Convergent),
+        1 => Some(BoundaryType::Divergent),
+        2 => Some(BoundaryType::Transform),
+        _ => None
+    }
+}
+
+/// Discrete event emitted by the plate simulation.
+#[deriving(Clone, Show)]
+pub struct Event {
+    pub step: uint,
+    pub kind: EventKind,
+    pub pos: Vector3<f32>,
+    /// Plates involved, indexed as they were when the event happened:
+    /// both sides of the boundary for earthquakes and arc eruptions, the
+    /// overriding plate first; the plate above the plume, twice, for
+    /// hotspot eruptions; both sides of the ridge, or the original and the
+    /// split off plate, for rifts;
+    /// the surviving and the absorbed plate for sutures.
+    pub plates: [uint, ..2],
+    /// Moment magnitude of earthquakes, 0 for other events.
+    pub magnitude: f32,
+    /// Boundary the event happened on, if any.
+    pub boundary_type: Option<BoundaryType>
+}
+
+impl Event {
+    pub fn new(step: uint,
+               kind: EventKind,
+               pos: &Vector3<f32>,
+               plates: [uint, ..2]) -> Event {
+        Event {
+            step: step,
+            kind: kind,
+            pos: *pos,
+            plates: plates,
+            magnitude: 0.0,
+            boundary_type: None
+        }
+    }
+
+    fn write_to<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        try!(write_uint(w, self.step));
+        try!(write_uint(w, self.kind.to_idx()));
+        try!(write_vec3(w, &self.pos));
+        try!(write_uint(w, self.plates[0]));
+        try!(write_uint(w, self.plates[1]));
+        try!(w.write_le_f32(self.magnitude));
+        match self.boundary_type {
+            Some(ref boundary_type) => {
+                try!(write_bool(w, true));
+                write_uint(w, boundary_type_to_idx(boundary_type))
+            },
+            None => write_bool(w, false)
+        }
+    }
+
+    fn read_from<R: Reader>(r: &mut R) -> IoResult<Event> {
+        let step = try!(read_uint(r));
+        let kind = match EventKind::from_idx(try!(read_uint(r))) {
+            Some(kind) => kind,
+            None => return Err(invalid_data("invalid event kind"))
+        };
+        let pos = try!(read_vec3(r));
+        let plates = [try!(read_uint(r)), try!(read_uint(r))];
+        let magnitude = try!(r.read_le_f32());
+        let boundary_type = if try!(read_bool(r)) {
+            match boundary_type_from_idx(try!(read_uint(r))) {
+                Some(boundary_type) => Some(boundary_type),
+                None => return Err(invalid_data("invalid event boundary type"))
+            }
+        } else {
+            None
+        };
+
+        Ok(Event {
+            step: step,
+            kind: kind,
+            pos: pos,
+            plates: plates,
+            magnitude: magnitude,
+            boundary_type: boundary_type
+        })
+    }
+}
+
+/// All events emitted so far, in the order they happened.
+pub struct EventLog {
+    events: Vec<Event>
+}
+
+impl EventLog {
+    pub fn new() -> EventLog {
+        EventLog {
+            events: Vec::new()
+        }
+    }
+
+    pub fn push(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    pub fn events(&self) -> &Vec<Event> {
+        &self.events
+    }
+
+    pub fn of_kind(&self, kind: EventKind) -> Vec<&Event> {
+        self.events.iter().filter(|e| e.kind == kind).collect()
+    }
+
+    /// Events that happened between `first_step` and `last_step`, inclusive.
+    pub fn in_steps(&self, first_step: uint, last_step: uint) -> Vec<&Event> {
+        self.events.iter()
+                   .filter(|e| e.step >= first_step && e.step <= last_step)
+                   .collect()
+    }
+
+    pub fn print_summary(&self) {
+        println!("plate events: {} earthquakes, {} eruptions, {} rifts, {} sutures",
+                 self.of_kind(EventKind::Earthquake).len(),
+                 self.of_kind(EventKind::Eruption).len(),
+                 self.of_kind(EventKind::Rift).len(),
+                 self.of_kind(EventKind::Suture).len());
+    }
+
+    pub fn write_csv<W: Writer>(&self,
+                                w: &mut W,
+                                step_myr: f32) -> IoResult<()> {
+        try!(writeln!(w, "step,time_myr,kind,lat,lon,plate_a,plate_b,magnitude,boundary_type"));
+        for e in self.events.iter() {
+            let (lat, lon) = lat_lon_deg(&e.pos);
+            let boundary_type = match e.boundary_type {
+                Some(ref boundary_type) => boundary_type.name(),
+                None => ""
+            };
+
+            try!(writeln!(w, "{},{},{},{},{},{},{},{},{}",
+                          e.step, e.step as f32 * step_myr, e.kind.name(), lat, lon,
+                          e.plates[0], e.plates[1], e.magnitude, boundary_type));
+        }
+        Ok(())
+    }
+
+    pub fn write_to<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        try!(write_uint(w, self.events.len()));
+        for event in self.events.iter() {
+            try!(event.write_to(w));
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Reader>(r: &mut R) -> IoResult<EventLog> {
+        let num_events = try!(read_uint(r));
+        let mut events = Vec::new();
+        for _ in range(0u, num_events) {
+            events.push(try!(Event::read_from(r)));
+        }
+
+        Ok(EventLog {
+            events: events
+        })
+    }
+}
+
+/// Slip deficit, in km of relative plate motion, after which a boundary
+/// segment ruptures.
+const RUPTURE_SLIP_KM: f32 = 100.0;
+
+/// Depth of the brittle crust that ruptures in earthquakes, in km.
+const SEISMOGENIC_DEPTH_KM: f32 = 15.0;
+
+/// Shear modulus of the crust, in Pa.
+const SHEAR_MODULUS: f32 = 3.0e10;
+
+/// Fraction of relative motion locked on the fault and released in
+/// earthquakes rather than by aseismic creep.
+fn seismic_coupling(boundary_type: &BoundaryType) -> f32 {
+    match *boundary_type {
+        BoundaryType::Convergent => 1.0,
+        BoundaryType::Transform => 0.8,
+        BoundaryType::Divergent => 0.1
+    }
+}
+
+/// Moment magnitude of a rupture of given area and average slip.
+pub fn moment_magnitude(area_km2: f32, slip_m: f32) -> f32 {
+    let moment = SHEAR_MODULUS * area_km2 * 1.0e6 * slip_m;
+    moment.log10() * 2.0 / 3.0 - 6.07
+}
+
+/// Slip deficit accumulated along plate boundaries, per pair of points on
+/// either side of a boundary segment.
+///
+/// A single step covers millions of years, so each rupture stands for the
+/// characteristic earthquake of a segment rather than an individual event.
+pub struct FaultStress {
+    slip_km: TreeMap<(uint, uint), f32>
+}
+
+impl FaultStress {
+    pub fn new() -> FaultStress {
+        FaultStress {
+            slip_km: TreeMap::new()
+        }
+    }
+
+    /// Loads boundary segments with a step of relative plate motion.
+    /// Returns indices of ruptured segments with earthquake magnitudes;
+    /// their stress is released. Segments that are no longer boundaries
+    /// lose their stress as well.
+    pub fn accumulate(&mut self,
+                      segments: &Vec<BoundarySegment>,
+                      step_myr: f32,
+                      planet_radius_km: f32) -> Vec<(uint, f32)> {
+        // cm/yr over a Myr -> km
+        const CM_PER_YR_MYR_TO_KM: f32 = 10.0;
+
+        let mut slip_km = TreeMap::new();
+        let mut ruptures = Vec::new();
+
+        for segment_idx in range(0u, segments.len()) {
+            let segment = &segments[segment_idx];
+            let key = (segment.vertex_indices[0], segment.vertex_indices[1]);
+            let slip = match self.slip_km.get(&key) {
+                Some(&slip) => slip,
+                None => 0.0
+            } + segment.relative_speed * step_myr * CM_PER_YR_MYR_TO_KM
+              * seismic_coupling(&segment.boundary_type);
+
+            if slip < RUPTURE_SLIP_KM {
+                slip_km.insert(key, slip);
+                continue;
+            }
+
+            let length_km = angle_between(&segment.endpoints[0], &segment.endpoints[1])
+                            * planet_radius_km;
+            if length_km > 0.0 {
+                // releasing exactly RUPTURE_SLIP_KM of deficit slips the fault by 1 m
+                let magnitude = moment_magnitude(length_km * SEISMOGENIC_DEPTH_KM,
+                                                 slip / RUPTURE_SLIP_KM);
+                ruptures.push((segment_idx, magnitude));
+            }
+        }
+
+        self.slip_km = slip_km;
+        ruptures
+    }
+
+    pub fn write_to<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        try!(write_uint(w, self.slip_km.len()));
+        for (&(a, b), &slip) in self.slip_km.iter() {
+            try!(write_uint(w, a));
+            try!(write_uint(w, b));
+            try!(w.write_le_f32(slip));
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Reader>(r: &mut R) -> IoResult<FaultStress> {
+        let mut slip_km = TreeMap::new();
+        for _ in range(0u, try!(read_uint(r))) {
+            let a = try!(read_uint(r));
+            let b = try!(read_uint(r));
+            slip_km.insert((a, b), try!(r.read_le_f32()));
+        }
+
+        Ok(FaultStress {
+            slip_km: slip_km
+        })
+    }
+}
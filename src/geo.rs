@@ -0,0 +1,38 @@
+extern crate cgmath;
+
+use std::num::{Float, FloatMath};
+
+use cgmath::{EuclideanVector, Vector, Vector3};
+
+/// Angle between two directions, in radians.
+pub fn angle_between(a: &Vector3<f32>,
+                     b: &Vector3<f32>) -> f32 {
+    a.normalize().dot(&b.normalize()).max(-1.0).min(1.0).acos()
+}
+
+/// Latitude and longitude of a direction, in degrees. The z axis points
+/// towards the north pole.
+pub fn lat_lon_deg(pos: &Vector3<f32>) -> (f32, f32) {
+    let pos = pos.normalize();
+    (pos.z.max(-1.0).min(1.0).asin().to_degrees(),
+     pos.y.atan2(pos.x).to_degrees())
+}
+
+/// Unit vector pointing at given latitude and longitude, in degrees.
+pub fn from_lat_lon_deg(lat: f32, lon: f32) -> Vector3<f32> {
+    let (sin_lat, cos_lat) = lat.to_radians().sin_cos();
+    let (sin_lon, cos_lon) = lon.to_radians().sin_cos();
+
+    Vector3::new(cos_lat * cos_lon, cos_lat * sin_lon, sin_lat)
+}
+
+/// Area of the spherical triangle spanned by three directions, as a
+/// fraction of the squared sphere radius. Computed from the spherical
+/// excess (Van Oosterom and Strackee, 1983).
+pub fn triangle_area(a: &Vector3<f32>,
+                     b: &Vector3<f32>,
+                     c: &Vector3<f32>) -> f32 {
+    let (a, b, c) = (a.normalize(), b.normalize(), c.normalize());
+    let triple = a.dot(&b.cross(&c)).abs();
+    2.0 * triple.atan2(1.0 + a.dot(&b) + b.dot(&c) + c.dot(&a))
+}
@@ -0,0 +1,82 @@
+extern crate cgmath;
+
+use std::num::{Float, FloatMath};
+use std::rand::Rng;
+use std::io::IoResult;
+
+use cgmath::{EuclideanVector, Vector, Vector3};
+
+use plate_simulation::random_pole;
+use checkpoint::{write_uint, read_uint, write_vec3, read_vec3};
+
+/// Smallest angular radius of the area affected by a single mantle plume,
+/// in radians.
+const MIN_PLUME_RADIUS: f32 = 0.1;
+
+/// Plume radius relative to the distance between neighboring points. A
+/// point is never farther than about 0.58 edge lengths from its nearest
+/// mesh point, so a plume this large always covers some crust.
+const PLUME_RADIUS_EDGES: f32 = 0.6;
+
+/// Angular radius of the area affected by a mantle plume on a mesh with
+/// given distance between neighboring points, in radians.
+pub fn plume_radius(edge_length: f32) -> f32 {
+    (edge_length * PLUME_RADIUS_EDGES).max(MIN_PLUME_RADIUS)
+}
+
+/// Mantle plume fixed in place under the moving plates.
+pub struct Hotspot {
+    pub pos: Vector3<f32>,
+    /// Uplift at the plume center, in height units per Myr.
+    pub strength: f32
+}
+
+impl Hotspot {
+    pub fn new<R: Rng>(rng: &mut R,
+                       max_strength: f32) -> Hotspot {
+        Hotspot {
+            pos: random_pole(rng),
+            strength: rng.gen_range(max_strength * 0.5, max_strength)
+        }
+    }
+
+    /// Uplift rate of the crust at `pos`, falling off linearly with the
+    /// distance from the plume center to 0 at `radius`.
+    pub fn uplift_at(&self, pos: &Vector3<f32>, radius: f32) -> f32 {
+        let dist = self.pos.dot(&pos.normalize()).max(-1.0).min(1.0).acos();
+        self.strength * (1.0 - dist / radius).max(0.0)
+    }
+
+    pub fn write_to<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        try!(write_vec3(w, &self.pos));
+        w.write_le_f32(self.strength)
+    }
+
+    pub fn read_from<R: Reader>(r: &mut R) -> IoResult<Hotspot> {
+        Ok(Hotspot {
+            pos: try!(read_vec3(r)),
+            strength: try!(r.read_le_f32())
+        })
+    }
+}
+
+/// Last eruption recorded on a piece of crust.
+#[deriving(Clone, Show)]
+pub struct VolcanicRecord {
+    pub hotspot_idx: uint,
+    pub last_step: uint
+}
+
+impl VolcanicRecord {
+    pub fn write_to<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        try!(write_uint(w, self.hotspot_idx));
+        write_uint(w, self.last_step)
+    }
+
+    pub fn read_from<R: Reader>(r: &mut R) -> IoResult<VolcanicRecord> {
+        Ok(VolcanicRecord {
+            hotspot_idx: try!(read_uint(r)),
+            last_step: try!(read_uint(r))
+        })
+    }
+}
@@ -0,0 +1,401 @@
+use std::vec::Vec;
+use std::collections::BinaryHeap;
+
+use domain::Topology;
+
+/// Minimum height difference between a filled vertex and the vertex it
+/// was reached from, so that filled depressions have no flats and every
+/// land vertex has a lower neighbor.
+const FILL_EPSILON: f32 = 1.0e-5;
+
+/// Priority queue entry ordered so that `BinaryHeap` pops the lowest vertex
+/// first. Ties are broken by vertex index to keep the result deterministic.
+struct FloodCell {
+    height: f32,
+    idx: uint
+}
+
+impl PartialEq for FloodCell {
+    fn eq(&self, other: &FloodCell) -> bool {
+        self.cmp(other) == Equal
+    }
+}
+impl Eq for FloodCell {}
+
+impl PartialOrd for FloodCell {
+    fn partial_cmp(&self, other: &FloodCell) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FloodCell {
+    fn cmp(&self, other: &FloodCell) -> Ordering {
+        if self.height < other.height {
+            Greater
+        } else if self.height > other.height {
+            Less
+        } else {
+            other.idx.cmp(&self.idx)
+        }
+    }
+}
+
+/// Raises every closed depression to its spill level (priority-flood),
+/// growing inwards from `outlets`. Every vertex ends up at least `epsilon`
+/// above the vertex it was reached from.
+fn priority_flood<T: Topology>(topology: &T,
+                               heights: &Vec<f32>,
+                               outlets: &Vec<bool>,
+                               epsilon: f32) -> Vec<f32> {
+    let mut filled = heights.clone();
+    let mut done = outlets.clone();
+    let mut queue = BinaryHeap::new();
+
+    for idx in range(0u, heights.len()) {
+        if outlets[idx] {
+            queue.push(FloodCell { height: heights[idx], idx: idx });
+        }
+    }
+
+    loop {
+        let cell = match queue.pop() {
+            Some(cell) => cell,
+            None => break
+        };
+
+        for &nbr in topology.neighbors(cell.idx).iter() {
+            if done[nbr] {
+                continue;
+            }
+
+            done[nbr] = true;
+            filled[nbr] = filled[nbr].max(filled[cell.idx] + epsilon);
+            queue.push(FloodCell { height: filled[nbr], idx: nbr });
+        }
+    }
+
+    filled
+}
+
+/// Vertices sorted from the highest to the lowest, by `heights`.
+fn sorted_downhill(heights: &Vec<f32>) -> Vec<uint> {
+    let mut order: Vec<uint> = range(0u, heights.len()).collect();
+    order.sort_by(|&a, &b| {
+        if heights[a] > heights[b] {
+            Less
+        } else if heights[a] < heights[b] {
+            Greater
+        } else {
+            a.cmp(&b)
+        }
+    });
+    order
+}
+
+/// River flowing from a source down to the sea or to a larger river.
+#[deriving(Clone, Show)]
+pub struct River {
+    /// Vertices along the river, downstream. The last one lies in the sea
+    /// or on the river this one flows into.
+    pub vertices: Vec<uint>,
+    /// Discharge at every vertex, in runoff units.
+    pub discharge: Vec<f32>
+}
+
+/// Water filling a closed depression.
+#[deriving(Clone, Show)]
+pub struct Lake {
+    /// Flooded vertices.
+    pub vertices: Vec<uint>,
+    /// Height of the water surface: the spill level of the depression.
+    pub surface_height: f32,
+    /// Vertex outside the lake that the lake overflows into. None for salt
+    /// lakes, which lose all their water to evaporation.
+    pub outflow: Option<uint>,
+    /// Discharge entering the lake, including runoff from the lake itself.
+    pub inflow: f32,
+    pub salt: bool
+}
+
+/// Surface water flow over a mesh.
+pub struct Drainage {
+    /// Heights with closed depressions filled up to their spill level.
+    pub filled: Vec<f32>,
+    /// True for vertices below sea level, where water leaves the land.
+    pub outlets: Vec<bool>,
+    /// Neighbor every land vertex drains to, along the steepest descent
+    /// over filled heights. None for outlets.
+    pub receivers: Vec<Option<uint>>,
+    /// Runoff from the vertex itself and everything upstream of it.
+    pub discharge: Vec<f32>,
+    /// Drainage basin of every land vertex. Basins draining to the sea are
+    /// numbered in the order of their outlet heights, endorheic basins
+    /// follow in the order of their lakes.
+    pub basins: Vec<Option<uint>>,
+    pub num_basins: uint,
+    pub rivers: Vec<River>,
+    /// Lakes ordered from the highest water surface to the lowest.
+    pub lakes: Vec<Lake>,
+    /// Lake index of every flooded vertex.
+    pub lake_of: Vec<Option<uint>>
+}
+
+impl Drainage {
+    /// Routes `runoff` from every vertex above `sea_level` to the sea.
+    /// Vertices with discharge of at least `river_threshold` form rivers.
+    /// If no vertex lies below sea level, everything drains to the lowest
+    /// vertex.
+    ///
+    /// Closed depressions fill with water up to their spill level, unless
+    /// `lake_evaporation` per flooded vertex exceeds their inflow. Such
+    /// lakes become salt lakes, and their catchment an endorheic basin
+    /// that never reaches the sea.
+    pub fn compute<T: Topology>(topology: &T,
+                                heights: &Vec<f32>,
+                                sea_level: f32,
+                                runoff: &Vec<f32>,
+                                river_threshold: f32,
+                                lake_evaporation: f32) -> Drainage {
+        let num_points = heights.len();
+
+        let mut outlets: Vec<bool> = heights.iter().map(|&h| h <= sea_level).collect();
+        if num_points > 0 && !outlets.iter().any(|&o| o) {
+            let lowest = range(1u, num_points).fold(0u, |best, i| {
+                if heights[i] < heights[best] { i } else { best }
+            });
+            outlets[lowest] = true;
+        }
+
+        let filled = priority_flood(topology, heights, &outlets, FILL_EPSILON);
+        let water = priority_flood(topology, heights, &outlets, 0.0);
+
+        let receivers: Vec<Option<uint>> = range(0u, num_points).map(|idx| {
+            if outlets[idx] {
+                return None;
+            }
+
+            let mut best = None;
+            let mut best_slope = 0.0f32;
+            for &nbr in topology.neighbors(idx).iter() {
+                let slope = (filled[idx] - filled[nbr]) / topology.distance(idx, nbr);
+                if slope > best_slope {
+                    best_slope = slope;
+                    best = Some(nbr);
+                }
+            }
+            best
+        }).collect();
+
+        let downhill = sorted_downhill(&filled);
+
+        let mut discharge: Vec<f32> = range(0u, num_points).map(|idx| {
+            if outlets[idx] { 0.0 } else { runoff[idx] }
+        }).collect();
+        for &idx in downhill.iter() {
+            match receivers[idx] {
+                Some(receiver) => discharge[receiver] += discharge[idx],
+                None => {}
+            }
+        }
+
+        let mut basins = Vec::from_elem(num_points, None);
+        let mut outlet_basins = Vec::from_elem(num_points, None);
+        let mut num_basins = 0u;
+        for &idx in downhill.iter().rev() {
+            match receivers[idx] {
+                Some(receiver) if outlets[receiver] => {
+                    if outlet_basins[receiver].is_none() {
+                        outlet_basins[receiver] = Some(num_basins);
+                        num_basins += 1;
+                    }
+                    basins[idx] = outlet_basins[receiver];
+                },
+                // receivers are lower, so their basin is already known
+                Some(receiver) => basins[idx] = basins[receiver],
+                None => {}
+            }
+        }
+
+        let (mut lakes, lake_of) = find_lakes(topology, heights, &water, &receivers);
+
+        // higher lakes first, so that water lost upstream is no longer
+        // counted as inflow to lakes downstream
+        let mut lake_basins = Vec::from_elem(num_points, None);
+        for lake in lakes.iter_mut() {
+            // all water leaves through the vertex with the largest discharge
+            let mut exit = None;
+            for &v in lake.vertices.iter() {
+                let leaves = match receivers[v] {
+                    Some(receiver) => lake_of[receiver] != lake_of[v],
+                    None => false
+                };
+                if leaves && exit.map_or(true, |e: uint| discharge[v] > discharge[e]) {
+                    exit = Some(v);
+                }
+            }
+            let exit = match exit {
+                Some(exit) => exit,
+                None => continue
+            };
+
+            lake.inflow = discharge[exit];
+            lake.outflow = receivers[exit];
+            if lake.inflow >= lake_evaporation * lake.vertices.len() as f32 {
+                continue;
+            }
+
+            lake.salt = true;
+            lake.outflow = None;
+            lake_basins[exit] = Some(num_basins);
+            num_basins += 1;
+
+            let mut downstream = receivers[exit];
+            loop {
+                match downstream {
+                    Some(idx) => {
+                        discharge[idx] -= lake.inflow;
+                        downstream = receivers[idx];
+                    },
+                    None => break
+                }
+            }
+        }
+
+        // vertices draining into a salt lake belong to its endorheic basin
+        for &idx in downhill.iter().rev() {
+            if lake_basins[idx].is_none() {
+                match receivers[idx] {
+                    Some(receiver) => lake_basins[idx] = lake_basins[receiver],
+                    None => {}
+                }
+            }
+            if lake_basins[idx].is_some() {
+                basins[idx] = lake_basins[idx];
+            }
+        }
+
+        let rivers = trace_rivers(&outlets, &receivers, &discharge, river_threshold);
+
+        Drainage {
+            filled: filled,
+            outlets: outlets,
+            receivers: receivers,
+            discharge: discharge,
+            basins: basins,
+            num_basins: num_basins,
+            rivers: rivers,
+            lakes: lakes,
+            lake_of: lake_of
+        }
+    }
+}
+
+/// Follows river vertices from every source downstream, until the sea or
+/// an already traced river.
+fn trace_rivers(outlets: &Vec<bool>,
+                receivers: &Vec<Option<uint>>,
+                discharge: &Vec<f32>,
+                river_threshold: f32) -> Vec<River> {
+    let num_points = outlets.len();
+    let is_river: Vec<bool> = range(0u, num_points).map(|idx| {
+        !outlets[idx] && discharge[idx] >= river_threshold
+    }).collect();
+
+    let mut has_upstream = Vec::from_elem(num_points, false);
+    for idx in range(0u, num_points) {
+        if is_river[idx] {
+            match receivers[idx] {
+                Some(receiver) => has_upstream[receiver] = true,
+                None => {}
+            }
+        }
+    }
+
+    let mut traced = Vec::from_elem(num_points, false);
+    let mut rivers = Vec::new();
+
+    for source in range(0u, num_points) {
+        if !is_river[source] || has_upstream[source] {
+            continue;
+        }
+
+        traced[source] = true;
+        let mut vertices = vec![source];
+        let mut idx = source;
+
+        loop {
+            let receiver = match receivers[idx] {
+                Some(receiver) => receiver,
+                None => break
+            };
+
+            // rivers ending in salt lakes dry up there
+            if !outlets[receiver] && !is_river[receiver] {
+                break;
+            }
+
+            vertices.push(receiver);
+            if outlets[receiver] || traced[receiver] {
+                break;
+            }
+
+            traced[receiver] = true;
+            idx = receiver;
+        }
+
+        rivers.push(River {
+            discharge: vertices.iter().map(|&i| discharge[i]).collect(),
+            vertices: vertices
+        });
+    }
+
+    rivers
+}
+
+/// Groups flooded vertices, where `water` lies above `heights`, into lakes
+/// of a common water surface. Lakes are ordered from the highest surface.
+/// Inflow and outflow are left for the caller to fill in.
+fn find_lakes<T: Topology>(topology: &T,
+                           heights: &Vec<f32>,
+                           water: &Vec<f32>,
+                           receivers: &Vec<Option<uint>>)
+        -> (Vec<Lake>, Vec<Option<uint>>) {
+    let num_points = heights.len();
+    let flooded = |idx: uint| receivers[idx].is_some() && water[idx] > heights[idx];
+
+    let mut lakes = Vec::new();
+    let mut lake_of = Vec::from_elem(num_points, None);
+
+    for &start in sorted_downhill(water).iter() {
+        if !flooded(start) || lake_of[start].is_some() {
+            continue;
+        }
+
+        let lake_idx = lakes.len();
+        let surface_height = water[start];
+        let mut vertices = vec![start];
+        lake_of[start] = Some(lake_idx);
+
+        let mut i = 0u;
+        while i < vertices.len() {
+            let idx = vertices[i];
+            for &nbr in topology.neighbors(idx).iter() {
+                if lake_of[nbr].is_none() && flooded(nbr) && water[nbr] == surface_height {
+                    lake_of[nbr] = Some(lake_idx);
+                    vertices.push(nbr);
+                }
+            }
+            i += 1;
+        }
+
+        lakes.push(Lake {
+            vertices: vertices,
+            surface_height: surface_height,
+            outflow: None,
+            inflow: 0.0,
+            salt: false
+        });
+    }
+
+    (lakes, lake_of)
+}
@@ -0,0 +1,263 @@
+extern crate cgmath;
+
+use std::vec::Vec;
+
+use cgmath::{EuclideanVector, Vector, Vector3};
+
+use domain::Topology;
+use geo::lat_lon_deg;
+use coastline::Coastlines;
+
+/// Labels points of `topology` for which `is_member` holds with a common
+/// index if they are connected through other members. Returns the labels
+/// and the number of components.
+pub fn label_components<T: Topology>(topology: &T,
+                                     is_member: |uint| -> bool) -> (Vec<Option<uint>>, uint) {
+    let num_points = topology.num_points();
+    let mut component_of = Vec::from_elem(num_points, None);
+    let mut num_components = 0u;
+
+    for start in range(0u, num_points) {
+        if component_of[start].is_some() || !is_member(start) {
+            continue;
+        }
+
+        component_of[start] = Some(num_components);
+        let mut to_visit = vec![start];
+
+        loop {
+            let idx = match to_visit.pop() {
+                Some(idx) => idx,
+                None => break
+            };
+
+            for &nbr in topology.neighbors(idx).iter() {
+                if component_of[nbr].is_none() && is_member(nbr) {
+                    component_of[nbr] = Some(num_components);
+                    to_visit.push(nbr);
+                }
+            }
+        }
+
+        num_components += 1;
+    }
+
+    (component_of, num_components)
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum LandClass {
+    Continent,
+    Island,
+    Islet
+}
+
+impl LandClass {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            LandClass::Continent => "continent",
+            LandClass::Island => "island",
+            LandClass::Islet => "islet"
+        }
+    }
+}
+
+/// Area limits between landmass classes, in km^2.
+#[deriving(Clone, Show)]
+pub struct LandThresholds {
+    /// Smallest continent. Greenland, the largest island, has about 2.2
+    /// million km^2, Australia, the smallest continent, 7.7 million.
+    pub continent_km2: f32,
+    /// Smallest island.
+    pub island_km2: f32
+}
+
+impl LandThresholds {
+    pub fn new() -> LandThresholds {
+        LandThresholds {
+            continent_km2: 5.0e6,
+            island_km2: 1.0e4
+        }
+    }
+
+    pub fn classify(&self, area_km2: f32) -> LandClass {
+        if area_km2 >= self.continent_km2 {
+            LandClass::Continent
+        } else if area_km2 >= self.island_km2 {
+            LandClass::Island
+        } else {
+            LandClass::Islet
+        }
+    }
+}
+
+/// Connected piece of land or water.
+#[deriving(Clone, Show)]
+pub struct Region {
+    pub vertices: Vec<uint>,
+    pub area_km2: f32,
+    /// Latitude and longitude of the area-weighted center, in degrees.
+    pub centroid_lat_lon: (f32, f32),
+    /// Total length of the coastlines bounding the region, filled in by
+    /// `Regions::set_perimeters`.
+    pub perimeter_km: f32,
+    /// Size class of landmasses; None for water bodies.
+    pub class: Option<LandClass>
+}
+
+/// Landmasses and water bodies of a world, each sorted from the largest.
+pub struct Regions {
+    pub landmasses: Vec<Region>,
+    pub water_bodies: Vec<Region>,
+    /// Landmass index of every land point.
+    pub landmass_of: Vec<Option<uint>>,
+    /// Water body index of every water point.
+    pub water_body_of: Vec<Option<uint>>
+}
+
+/// Splits points for which `in_region` holds into connected regions sorted
+/// by decreasing area. Returns the regions and the region of every point.
+fn find_regions<T: Topology>(topology: &T,
+                             positions: &Vec<Vector3<f32>>,
+                             vertex_areas: &Vec<f32>,
+                             in_region: |uint| -> bool,
+                             planet_radius_km: f32) -> (Vec<Region>, Vec<Option<uint>>) {
+    let (component_of, num_components) = label_components(topology, in_region);
+    let radius_sq = planet_radius_km * planet_radius_km;
+
+    let mut vertices = Vec::from_fn(num_components, |_| Vec::new());
+    let mut areas = Vec::from_elem(num_components, 0.0f32);
+    let mut centers = Vec::from_elem(num_components, Vector3::new(0.0f32, 0.0, 0.0));
+
+    for idx in range(0u, component_of.len()) {
+        let component = match component_of[idx] {
+            Some(component) => component,
+            None => continue
+        };
+
+        vertices[component].push(idx);
+        areas[component] += vertex_areas[idx] * radius_sq;
+        centers[component] = centers[component].add(&positions[idx].normalize()
+                                                                   .mul_s(vertex_areas[idx]));
+    }
+
+    let mut order: Vec<uint> = range(0u, num_components).collect();
+    order.sort_by(|&a, &b| {
+        if areas[a] > areas[b] {
+            Less
+        } else if areas[a] < areas[b] {
+            Greater
+        } else {
+            a.cmp(&b)
+        }
+    });
+
+    let mut new_idx = Vec::from_elem(num_components, 0u);
+    for (rank, &component) in order.iter().enumerate() {
+        new_idx[component] = rank;
+    }
+
+    let regions = order.iter().map(|&component| Region {
+        vertices: vertices[component].clone(),
+        area_km2: areas[component],
+        centroid_lat_lon: lat_lon_deg(&centers[component]),
+        perimeter_km: 0.0,
+        class: None
+    }).collect();
+    let region_of = component_of.iter().map(|c| c.map(|c| new_idx[c])).collect();
+
+    (regions, region_of)
+}
+
+impl Regions {
+    /// Finds landmasses among points where `is_land` holds and water
+    /// bodies among the rest. `vertex_areas` are fractions of the squared
+    /// planet radius.
+    pub fn compute<T: Topology>(topology: &T,
+                                positions: &Vec<Vector3<f32>>,
+                                vertex_areas: &Vec<f32>,
+                                is_land: &Vec<bool>,
+                                planet_radius_km: f32,
+                                thresholds: &LandThresholds) -> Regions {
+        let (mut landmasses, landmass_of) = find_regions(topology, positions, vertex_areas,
+                                                         |idx| is_land[idx], planet_radius_km);
+        let (water_bodies, water_body_of) = find_regions(topology, positions, vertex_areas,
+                                                         |idx| !is_land[idx], planet_radius_km);
+
+        for landmass in landmasses.iter_mut() {
+            landmass.class = Some(thresholds.classify(landmass.area_km2));
+        }
+
+        Regions {
+            landmasses: landmasses,
+            water_bodies: water_bodies,
+            landmass_of: landmass_of,
+            water_body_of: water_body_of
+        }
+    }
+
+    /// Sets the perimeter of every landmass and water body to the length of
+    /// the `coastlines` between them, traced around the same regions.
+    pub fn set_perimeters(&mut self, coastlines: &Coastlines) {
+        for (idx, landmass) in self.landmasses.iter_mut().enumerate() {
+            landmass.perimeter_km = coastlines.landmass_coastline_km(idx);
+        }
+        for (idx, water_body) in self.water_bodies.iter_mut().enumerate() {
+            water_body.perimeter_km = coastlines.water_body_coastline_km(idx);
+        }
+    }
+
+    pub fn print_summary(&self) {
+        let count = |class: LandClass| {
+            self.landmasses.iter().filter(|l| l.class == Some(class.clone())).count()
+        };
+        println!("{} landmasses ({} continents, {} islands, {} islets), {} water bodies",
+                 self.landmasses.len(), count(LandClass::Continent),
+                 count(LandClass::Island), count(LandClass::Islet),
+                 self.water_bodies.len());
+
+        for (idx, landmass) in self.landmasses.iter().take(5).enumerate() {
+            let (lat, lon) = landmass.centroid_lat_lon;
+            println!("- landmass {}: {}, {} km^2, centered at {}, {}, perimeter {} km",
+                     idx, landmass.class.as_ref().unwrap().name(), landmass.area_km2,
+                     lat, lon, landmass.perimeter_km);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+    use std::num::Float;
+
+    use polyhedron::make_sphere;
+    use world::World;
+
+    use super::LandThresholds;
+
+    #[test]
+    fn perimeters_follow_coastlines() {
+        let mut world = World::new(make_sphere(4));
+
+        // polar caps above 0.8 in an ocean at sea level 1.0
+        let heights: Vec<f32> = world.positions().iter().map(|pos| {
+            if pos.z.abs() > 0.8 { 1.5 } else { 0.5 }
+        }).collect();
+        world.set_heights(&heights);
+
+        let mut regions = world.regions(1.0, &LandThresholds::new());
+        let coastlines = world.coastlines(&regions, 1.0);
+        regions.set_perimeters(&coastlines);
+
+        assert_eq!(regions.landmasses.len(), 2);
+        assert_eq!(regions.water_bodies.len(), 1);
+
+        let cap_perimeter = 2.0 * PI * 0.6;
+        for landmass in regions.landmasses.iter() {
+            assert!((landmass.perimeter_km - cap_perimeter).abs() < 0.25 * cap_perimeter,
+                    "perimeter {} instead of {}", landmass.perimeter_km, cap_perimeter);
+        }
+        let land_perimeter = regions.landmasses.iter().fold(0.0f32, |sum, l| sum + l.perimeter_km);
+        assert!((regions.water_bodies[0].perimeter_km - land_perimeter).abs() < 1.0e-3);
+    }
+}
@@ -3,6 +3,7 @@
 
 extern crate time;
 extern crate getopts;
+extern crate serialize;
 
 extern crate glfw;
 extern crate gfx;
@@ -13,8 +14,9 @@ extern crate gfx_macros;
 extern crate render;
 extern crate device;
 
-use std::rand::{SeedableRng, XorShiftRng};
+use std::rand::SeedableRng;
 use std::os;
+use std::io::{File, stdio};
 
 use gfx::batch;
 use gfx::{Device, DeviceHelper};
@@ -24,8 +26,17 @@ use cgmath::{Point3, Vector3, Matrix4, FixedArray, AffineMatrix3, Transform};
 
 use collisions::{intersecting_triangle_id, Ray};
 use world::World;
-use rendering::{PolyhedronBatch, Uniforms};
-use plate_simulation::PlateSimulation;
+use rendering::{PolyhedronBatch, Uniforms, OCEAN_COLOR, LAKE_COLOR};
+use plate_simulation::{PlateSimulation, PlateSimParams};
+use sim_rng::SimRng;
+use plate_stats::StepStats;
+use plate_layout::PlateLayout;
+use erosion::{ThermalErosion, HydraulicErosion};
+use coastline::Coastlines;
+use climate::PrecipitationModel;
+use world::Attribute;
+use biome::BiomeTable;
+use scenario::{Scenario, SupercontinentCycle, SUPERCONTINENT_CONVECTION_INTERVAL};
 
 mod camera;
 mod polyhedron;
@@ -33,7 +44,30 @@ mod collisions;
 mod world;
 mod rendering;
 mod plate_simulation;
+mod parallel;
+mod geo;
+mod plate_stats;
+mod plate_graph;
+mod plate_layout;
+mod partition;
+mod mantle_convection;
+mod hotspot;
 mod cmdline;
+mod sim_rng;
+mod checkpoint;
+mod reconstruction;
+mod scenario;
+mod events;
+mod erosion;
+mod hydrology;
+mod sea_level;
+mod coastline;
+mod landmass;
+mod climate;
+mod biome;
+mod domain;
+#[cfg(test)]
+mod torus;
 
 include!("macros.rs")
 
@@ -56,65 +90,410 @@ struct GameState<'a> {
 
     plate_sim_point_batches: Vec<(PolyhedronBatch, batch::Context)>,
     plate_sim_world_batches: Vec<(PolyhedronBatch, batch::Context)>,
+    plate_sim_boundary_batches: Vec<(PolyhedronBatch, batch::Context)>,
+    show_boundaries: bool,
 
     world: World,
     world_batch: (PolyhedronBatch, batch::Context),
+    biome_batch: Option<(PolyhedronBatch, batch::Context)>,
+    show_biomes: bool,
+}
+
+/// Random number generator for world generation stages. Derived from the
+/// seed rather than shared with the plate simulation, so that worlds built
+/// after every simulation step are reproducible.
+fn world_rng(cmdline_args: &cmdline::Args) -> SimRng {
+    const WORLD_SEED_MASK: [u32, ..4] = [0x9e3779b9, 0x7f4a7c15, 0xf39cc060, 0x5ced5f6b];
+
+    let mut seed = cmdline_args.rng_seed_hash;
+    for i in range(0u, 4) {
+        seed[i] ^= WORLD_SEED_MASK[i];
+    }
+    SeedableRng::from_seed(seed)
+}
+
+fn load_biome_table(cmdline_args: &cmdline::Args) -> BiomeTable {
+    match cmdline_args.biome_table {
+        Some(ref path) => match BiomeTable::load(&Path::new(path.as_slice())) {
+            Ok(table) => table,
+            Err(e) => {
+                panic_bt!("cannot use biome table: {}", e);
+            }
+        },
+        None => BiomeTable::whittaker()
+    }
 }
 
 fn world_from_plate_sim(sim: &PlateSimulation,
-                        detail_level: uint) -> World {
-    let world_poly = polyhedron::make_sphere(detail_level);
+                        cmdline_args: &cmdline::Args) -> World {
+    let world_poly = polyhedron::make_sphere(cmdline_args.world_detail_level);
     let mut world = World::new(world_poly);
 
     time_it!("world.apply_heights", 0.5f64, {
-        world.apply_heights(sim);
+        world.apply_heights(sim, cmdline_args.num_threads);
     });
 
+    if cmdline_args.hydraulic_erosion_droplets > 0 {
+        let mut rng = world_rng(cmdline_args);
+        let erosion = HydraulicErosion::new(cmdline_args.hydraulic_erosion_droplets);
+        time_it!("world.erode_hydraulic", 0.5f64, {
+            let report = world.erode_hydraulic(&erosion, &mut rng);
+            println!("hydraulic erosion moved {} height units ({} deposited)",
+                     report.eroded, report.deposited);
+        });
+    }
+
+    if cmdline_args.thermal_erosion_iterations > 0 {
+        let erosion = ThermalErosion {
+            talus_angle_deg: cmdline_args.talus_angle_deg,
+            strength: cmdline_args.thermal_erosion_strength,
+            ..ThermalErosion::new(cmdline_args.thermal_erosion_iterations)
+        };
+        time_it!("world.erode_thermal", 0.5f64, {
+            let moved = world.erode_thermal(&erosion);
+            println!("thermal erosion moved {} height units", moved);
+        });
+    }
+
+    world.set_sea_level(&cmdline_args.sea_level).print();
+
+    if cmdline_args.river_threshold > 0.0 {
+        time_it!("world.drainage", 0.5f64, {
+            let drainage = world.drainage(cmdline_args.river_threshold,
+                                          cmdline_args.lake_evaporation);
+            let max_discharge = drainage.discharge.iter().fold(0.0f32, |a, &b| a.max(b));
+            println!("{} rivers in {} drainage basins, largest discharge {}",
+                     drainage.rivers.len(), drainage.num_basins, max_discharge);
+            println!("{} lakes ({} salt)",
+                     drainage.lakes.len(),
+                     drainage.lakes.iter().filter(|l| l.salt).count());
+            world.set_lakes(&drainage);
+        });
+    }
+
     world
 }
 
-fn sim_to_point_world_batches(sim: &PlateSimulation,
-                              dev: &mut gfx::GlDevice,
-                              cmdline_args: &cmdline::Args)
+/// Adds temperature, precipitation and biomes from `biome_table` to the
+/// final world. Too slow to repeat for the worlds shown after every
+/// simulation step.
+fn add_climate(world: &mut World,
+               biome_table: &BiomeTable,
+               cmdline_args: &cmdline::Args) {
+    time_it!("world.set_temperature", 0.5f64, {
+        world.set_temperature(&cmdline_args.temperature_model, cmdline_args.planet_radius_km);
+    });
+    match world.attribute("temperature") {
+        Some(&Attribute::Scalar(ref temperature)) => {
+            let min = temperature.iter().fold(temperature[0], |a, &b| a.min(b));
+            let max = temperature.iter().fold(temperature[0], |a, &b| a.max(b));
+            println!("temperature from {} to {} C", min, max);
+        },
+        _ => {}
+    }
+
+    time_it!("world.set_precipitation", 0.5f64, {
+        world.set_precipitation(&PrecipitationModel::new());
+    });
+    match world.attribute("precipitation") {
+        Some(&Attribute::Scalar(ref precipitation)) => {
+            // less than 250 mm a year makes a desert
+            const DESERT_MM: f32 = 250.0;
+
+            let heights = world.heights();
+            let land: Vec<f32> = range(0u, heights.len())
+                                     .filter(|&i| heights[i] > world.sea_level())
+                                     .map(|i| precipitation[i])
+                                     .collect();
+            if !land.is_empty() {
+                let mean = land.iter().fold(0.0f32, |a, &b| a + b) / land.len() as f32;
+                let num_desert = land.iter().filter(|&&p| p < DESERT_MM).count();
+                println!("land precipitation {} mm/yr on average, {}% desert",
+                         mean, num_desert as f32 / land.len() as f32 * 100.0);
+            }
+        },
+        _ => {}
+    }
+
+    world.set_biomes(biome_table);
+}
+
+fn sim_to_batches(sim: &PlateSimulation,
+                  dev: &mut gfx::GlDevice,
+                  cmdline_args: &cmdline::Args)
         -> ((PolyhedronBatch, batch::Context),
             (PolyhedronBatch, batch::Context),
             World) {
-    let mut point_ctx = batch::Context::new();
     let mut world_ctx = batch::Context::new();
-    let world = world_from_plate_sim(sim, cmdline_args.world_detail_level);
+    let mut boundary_ctx = batch::Context::new();
+    let world = world_from_plate_sim(sim, cmdline_args);
 
-    ((sim.to_batch(&mut point_ctx, dev), point_ctx),
-     (world.to_batch(&mut world_ctx, dev), world_ctx),
+    ((world.to_batch(&mut world_ctx, dev), world_ctx),
+     (sim.boundaries_to_batch(&mut boundary_ctx, dev), boundary_ctx),
      world)
 }
 
+fn export_boundaries(sim: &PlateSimulation,
+                     path: &str) {
+    let result = File::create(&Path::new(path))
+                     .and_then(|mut file| sim.write_boundaries_obj(&mut file));
+
+    match result {
+        Ok(_) => println!("plate boundaries written to {}", path),
+        Err(e) => println_err!("cannot write plate boundaries to {}: {}", path, e)
+    }
+}
+
+fn new_plate_sim(cmdline_args: &cmdline::Args) -> (PlateSimulation, SimRng) {
+    let mut rng: SimRng = SeedableRng::from_seed(cmdline_args.rng_seed_hash);
+    let plate_sim_poly = polyhedron::make_sphere(cmdline_args.plate_sim_detail_level);
+    let plate_sim_params = PlateSimParams {
+        planet_radius_km: cmdline_args.planet_radius_km,
+        step_myr: cmdline_args.plate_step_myr,
+        convection_interval: match (cmdline_args.scenario, cmdline_args.convection_interval) {
+            // rifted continents only reassemble if driven by convection
+            (Some(Scenario::Supercontinent), 0) => SUPERCONTINENT_CONVECTION_INTERVAL,
+            (_, interval) => interval
+        },
+        partition: cmdline_args.partition,
+        num_hotspots: cmdline_args.num_hotspots,
+        hotspot_strength: cmdline_args.hotspot_strength,
+        num_threads: cmdline_args.num_threads,
+        ..PlateSimParams::new(cmdline_args.plate_sim_plates)
+    };
+    let plate_sim = match cmdline_args.plate_layout {
+        Some(_) if cmdline_args.scenario.is_some() => {
+            panic_bt!("plate layouts cannot be combined with scenarios");
+        },
+        Some(ref path) => {
+            let result = PlateLayout::load(&Path::new(path.as_slice())).and_then(|layout| {
+                PlateSimulation::from_layout(&plate_sim_poly, &plate_sim_params, &layout, &mut rng)
+            });
+            match result {
+                Ok(plate_sim) => plate_sim,
+                Err(e) => {
+                    panic_bt!("cannot use plate layout: {}", e);
+                }
+            }
+        },
+        None => match cmdline_args.scenario {
+            Some(Scenario::Supercontinent) => {
+                let continental_fraction = SupercontinentCycle::new().continental_fraction;
+                PlateSimulation::supercontinent(&plate_sim_poly, &plate_sim_params,
+                                                continental_fraction, &mut rng)
+            },
+            None => PlateSimulation::new(&plate_sim_poly, &plate_sim_params, &mut rng)
+        }
+    };
+
+    (plate_sim, rng)
+}
+
+fn load_plate_sim(path: &str) -> (PlateSimulation, SimRng) {
+    match checkpoint::load(&Path::new(path)) {
+        Ok((plate_sim, rng)) => {
+            println!("resuming plate simulation from {} at step {}", path, plate_sim.step());
+            (plate_sim, rng)
+        },
+        Err(e) => {
+            panic_bt!("cannot load checkpoint {}: {}", path, e);
+        }
+    }
+}
+
+fn save_plate_sim(path: &str,
+                  plate_sim: &PlateSimulation,
+                  rng: &SimRng) {
+    match checkpoint::save(&Path::new(path), plate_sim, rng) {
+        Ok(_) => println!("checkpoint at step {} written to {}", plate_sim.step(), path),
+        Err(e) => println_err!("cannot write checkpoint to {}: {}", path, e)
+    }
+}
+
+fn print_stats(plate_sim: &PlateSimulation,
+               cmdline_args: &cmdline::Args) {
+    match cmdline_args.stats_format {
+        Some(format) => {
+            let stats = StepStats::compute(plate_sim);
+            match stats.write_to(&mut stdio::stdout(), format) {
+                Err(e) => println_err!("cannot print plate statistics: {}", e),
+                _ => {}
+            }
+        },
+        None => {}
+    }
+}
+
+fn export_plate_graph(sim: &PlateSimulation,
+                      path: &str) {
+    let result = File::create(&Path::new(path))
+                     .and_then(|mut file| sim.adjacency_graph().write_dot(&mut file));
+
+    match result {
+        Ok(_) => println!("plate adjacency graph written to {}", path),
+        Err(e) => println_err!("cannot write plate adjacency graph to {}: {}", path, e)
+    }
+}
+
+fn export_cycle(cycle: &SupercontinentCycle,
+                path: &str) {
+    let result = File::create(&Path::new(path))
+                     .and_then(|mut file| cycle.write_csv(&mut file));
+
+    match result {
+        Ok(_) => println!("supercontinent cycle metrics written to {}", path),
+        Err(e) => println_err!("cannot write supercontinent cycle metrics to {}: {}", path, e)
+    }
+}
+
+fn export_events(sim: &PlateSimulation,
+                 path: &str) {
+    let result = File::create(&Path::new(path))
+                     .and_then(|mut file| sim.events().write_csv(&mut file, sim.step_myr()));
+
+    match result {
+        Ok(_) => println!("plate events written to {}", path),
+        Err(e) => println_err!("cannot write plate events to {}: {}", path, e)
+    }
+}
+
+fn export_coastlines(coastlines: &Coastlines,
+                     path: &str) {
+    let result = File::create(&Path::new(path))
+                     .and_then(|mut file| coastlines.write_csv(&mut file));
+
+    match result {
+        Ok(_) => println!("coastlines written to {}", path),
+        Err(e) => println_err!("cannot write coastlines to {}: {}", path, e)
+    }
+}
+
+fn export_attribute(world: &World,
+                    name: &str,
+                    path: &str) {
+    let result = File::create(&Path::new(path))
+                     .and_then(|mut file| world.write_attribute_csv(name, &mut file));
+
+    match result {
+        Ok(_) => println!("{} written to {}", name, path),
+        Err(e) => println_err!("cannot write {} to {}: {}", name, path, e)
+    }
+}
+
 fn generate_world(cmdline_args: &cmdline::Args,
                   dev: &mut gfx::GlDevice)
         -> (Vec<(PolyhedronBatch, batch::Context)>,
             Vec<(PolyhedronBatch, batch::Context)>,
-            World) {
-    let mut rng: XorShiftRng = SeedableRng::from_seed(cmdline_args.rng_seed_hash);
-    let plate_sim_poly = polyhedron::make_sphere(cmdline_args.plate_sim_detail_level);
-    let mut plate_sim = PlateSimulation::new(&plate_sim_poly,
-                                             cmdline_args.plate_sim_plates,
-                                             &mut rng);
+            Vec<(PolyhedronBatch, batch::Context)>,
+            World,
+            BiomeTable) {
+    let biome_table = load_biome_table(cmdline_args);
+    let (mut plate_sim, mut rng) = match cmdline_args.resume {
+        Some(ref path) => load_plate_sim(path.as_slice()),
+        None => new_plate_sim(cmdline_args)
+    };
+    plate_sim.set_num_threads(cmdline_args.num_threads);
+    let first_step = plate_sim.step();
+
+    let mut cycle = match cmdline_args.scenario {
+        Some(Scenario::Supercontinent) => {
+            let mut cycle = SupercontinentCycle::new();
+            cycle.start(&plate_sim);
+            Some(cycle)
+        },
+        None => None
+    };
+
+    // when resuming, plate_sim_steps is the total number of steps
+    let steps = if cmdline_args.plate_sim_steps > plate_sim.step() {
+        cmdline_args.plate_sim_steps - plate_sim.step()
+    } else {
+        0
+    };
 
-    let mut point_batches = Vec::with_capacity(cmdline_args.plate_sim_steps);
-    let mut world_batches = Vec::with_capacity(cmdline_args.plate_sim_steps);
+    let mut world_batches = Vec::with_capacity(steps);
+    let mut boundary_batches = Vec::with_capacity(steps);
 
-    for _ in range(0u, cmdline_args.plate_sim_steps) {
-        let (point_batch_ctx, world_batch_ctx, _) = sim_to_point_world_batches(&plate_sim, dev, cmdline_args);
-        point_batches.push(point_batch_ctx);
+    for _ in range(0u, steps) {
+        let (world_batch_ctx, boundary_batch_ctx, _) = sim_to_batches(&plate_sim, dev, cmdline_args);
         world_batches.push(world_batch_ctx);
+        boundary_batches.push(boundary_batch_ctx);
+        print_stats(&plate_sim, cmdline_args);
 
-        plate_sim.simulate_plates(1);
+        match cycle {
+            Some(ref mut cycle) => cycle.step(&mut plate_sim, &mut rng),
+            None => plate_sim.simulate_plates(1)
+        }
     }
 
-    let (point_batch_ctx, world_batch_ctx, world) = sim_to_point_world_batches(&plate_sim, dev, cmdline_args);
-    point_batches.push(point_batch_ctx);
+    let (world_batch_ctx, boundary_batch_ctx, mut world) = sim_to_batches(&plate_sim, dev, cmdline_args);
     world_batches.push(world_batch_ctx);
+    boundary_batches.push(boundary_batch_ctx);
+    print_stats(&plate_sim, cmdline_args);
+
+    // point batches are reconstructed from recorded plate rotations instead
+    // of being baked while simulating, stepping back once per step from the
+    // final positions; every step of this run is recorded
+    let mut positions = plate_sim.reconstruct(plate_sim.step()).unwrap();
+    let mut point_batches = Vec::with_capacity(plate_sim.step() - first_step + 1);
+    for step in range(first_step, plate_sim.step() + 1).rev() {
+        if step < plate_sim.step() {
+            plate_sim.step_back(&mut positions, step);
+        }
+        let mut ctx = batch::Context::new();
+        point_batches.push((plate_sim.to_batch(&mut ctx, dev, step, &positions).unwrap(), ctx));
+    }
+    point_batches.reverse();
+
+    plate_sim.print_velocity_report();
+    plate_sim.events().print_summary();
+
+    match cycle {
+        Some(ref cycle) => {
+            cycle.print_summary();
+            match cmdline_args.export_cycle {
+                Some(ref path) => export_cycle(cycle, path.as_slice()),
+                None => {}
+            }
+        },
+        None => {}
+    }
 
-    (point_batches, world_batches, world)
+    match cmdline_args.checkpoint {
+        Some(ref path) => save_plate_sim(path.as_slice(), &plate_sim, &rng),
+        None => {}
+    }
+
+    match cmdline_args.export_boundaries {
+        Some(ref path) => export_boundaries(&plate_sim, path.as_slice()),
+        None => {}
+    }
+    match cmdline_args.export_plate_graph {
+        Some(ref path) => export_plate_graph(&plate_sim, path.as_slice()),
+        None => {}
+    }
+    match cmdline_args.export_events {
+        Some(ref path) => export_events(&plate_sim, path.as_slice()),
+        None => {}
+    }
+
+    add_climate(&mut world, &biome_table, cmdline_args);
+
+    let mut regions = world.regions(cmdline_args.planet_radius_km, &cmdline_args.land_thresholds);
+    let coastlines = world.coastlines(&regions, cmdline_args.planet_radius_km);
+    regions.set_perimeters(&coastlines);
+    regions.print_summary();
+    coastlines.print_summary();
+    match cmdline_args.export_coastlines {
+        Some(ref path) => export_coastlines(&coastlines, path.as_slice()),
+        None => {}
+    }
+    match cmdline_args.export_biomes {
+        Some(ref path) => export_attribute(&world, "biome", path.as_slice()),
+        None => {}
+    }
+
+    (point_batches, world_batches, boundary_batches, world, biome_table)
 }
 
 impl<'a> GameState<'a> {
@@ -132,9 +511,16 @@ impl<'a> GameState<'a> {
         let mut dev = gfx::GlDevice::new(|s| wnd.get_proc_address(s));
         let renderer = dev.create_renderer();
 
-        let (point_batches, world_batches, world) = generate_world(cmdline_args, &mut dev);
+        let (point_batches, world_batches, boundary_batches, world, biome_table) = generate_world(cmdline_args, &mut dev);
         let mut world_ctx = batch::Context::new();
         let world_batch = world.to_batch(&mut world_ctx, &mut dev);
+        let mut biome_ctx = batch::Context::new();
+        let biome_colors = biome_table.colors(OCEAN_COLOR, LAKE_COLOR);
+        let biome_batch = match world.to_category_batch(&mut biome_ctx, &mut dev,
+                                                        "biome", &biome_colors) {
+            Some(batch) => Some((batch, biome_ctx)),
+            None => None
+        };
 
         GameState {
             wnd: wnd,
@@ -152,8 +538,12 @@ impl<'a> GameState<'a> {
             display_idx: point_batches.len() - 1,
             plate_sim_point_batches: point_batches,
             plate_sim_world_batches: world_batches,
+            plate_sim_boundary_batches: boundary_batches,
+            show_boundaries: false,
             world: world,
             world_batch: (world_batch, world_ctx),
+            biome_batch: biome_batch,
+            show_biomes: false,
         }
     }
 
@@ -201,6 +591,10 @@ impl<'a> GameState<'a> {
                      self.toggle_display_idx(1),
                 (glfw::Key::Space, glfw::Action::Press) =>
                     self.toggle_display_state(),
+                (glfw::Key::B, glfw::Action::Press) =>
+                    self.show_boundaries = !self.show_boundaries,
+                (glfw::Key::V, glfw::Action::Press) =>
+                    self.show_biomes = !self.show_biomes,
                 _ => {}
             },
             _ => {}
@@ -260,13 +654,19 @@ fn game_loop<'a>(game: &mut GameState<'a>,
         time_it!("render frame", 0.02f64, {
             game.renderer.clear(clear_data, gfx::COLOR | gfx::DEPTH, frame);
 
-            let &(ref batch, ref ctx) = match game.display_state {
-                DisplayState::World          => &game.world_batch,
-                DisplayState::PlateSimWorld  => &game.plate_sim_world_batches[game.display_idx],
-                DisplayState::PlateSimPoints => &game.plate_sim_point_batches[game.display_idx],
+            let &(ref batch, ref ctx) = match (&game.display_state, &game.biome_batch) {
+                (&DisplayState::World, &Some(ref biome_batch)) if game.show_biomes => biome_batch,
+                (&DisplayState::World, _)          => &game.world_batch,
+                (&DisplayState::PlateSimWorld, _)  => &game.plate_sim_world_batches[game.display_idx],
+                (&DisplayState::PlateSimPoints, _) => &game.plate_sim_point_batches[game.display_idx],
             };
 
             game.renderer.draw((batch, &game.uniforms, ctx), frame);
+
+            if game.show_boundaries {
+                let (ref batch, ref ctx) = game.plate_sim_boundary_batches[game.display_idx];
+                game.renderer.draw((batch, &game.uniforms, ctx), frame);
+            }
             game.dev.submit(game.renderer.as_buffer());
             game.renderer.reset();
 
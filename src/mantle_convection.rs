@@ -0,0 +1,152 @@
+extern crate cgmath;
+
+use std::vec::Vec;
+use std::num::Float;
+use std::rand::Rng;
+use std::io::IoResult;
+
+use cgmath::{EuclideanVector, Vector, Vector3, Matrix, Matrix3};
+
+use plate_simulation::random_pole;
+use checkpoint::{write_uint, read_uint, write_vec3, read_vec3};
+
+/// A single upwelling (positive strength) or downwelling (negative
+/// strength) of the mantle flow.
+struct ConvectionCell {
+    center: Vector3<f32>,
+    strength: f32
+}
+
+/// Simple low-order model of mantle convection: a handful of broad cells
+/// whose potential is a smooth bump around each cell center. The surface
+/// flow runs down the potential gradient, from upwellings to downwellings.
+pub struct MantleConvection {
+    cells: Vec<ConvectionCell>,
+    /// Number of simulation steps between plate velocity updates.
+    pub interval: uint,
+    /// Strength of slab pull at subduction zones, in radians per Myr.
+    pub slab_pull: f32,
+    /// Fraction of the difference between current and driven rotation
+    /// applied at every update. Lower values make plates more sluggish.
+    pub response: f32
+}
+
+/// Controls how broad the cells are. Small values keep the field low-order.
+const CELL_SHARPNESS: f32 = 2.0;
+
+fn tangential(v: &Vector3<f32>, pos: &Vector3<f32>) -> Vector3<f32> {
+    v.sub(&pos.mul_s(v.dot(pos)))
+}
+
+impl MantleConvection {
+    pub fn new<R: Rng>(rng: &mut R,
+                       interval: uint) -> MantleConvection {
+        const NUM_CELLS: uint = 6;
+        // peak flow of roughly 1 deg/Myr
+        const MAX_STRENGTH: f32 = 0.01;
+
+        let cells = range(0u, NUM_CELLS).map(|i| {
+            let sign = if i % 2 == 0 { 1.0f32 } else { -1.0 };
+            ConvectionCell {
+                center: random_pole(rng),
+                strength: sign * rng.gen_range(MAX_STRENGTH * 0.5, MAX_STRENGTH)
+            }
+        }).collect();
+
+        MantleConvection {
+            cells: cells,
+            interval: interval,
+            slab_pull: 0.005,
+            response: 0.5
+        }
+    }
+
+    /// Surface flow velocity at `pos`, in radians per Myr on the unit sphere.
+    pub fn flow_at(&self, pos: &Vector3<f32>) -> Vector3<f32> {
+        let pos = pos.normalize();
+        let mut gradient = Vector3::new(0.0f32, 0.0, 0.0);
+
+        for cell in self.cells.iter() {
+            let weight = (CELL_SHARPNESS * (cell.center.dot(&pos) - 1.0)).exp();
+            gradient = gradient.add(&cell.center.mul_s(cell.strength * CELL_SHARPNESS * weight));
+        }
+
+        tangential(&gradient, &pos).neg()
+    }
+
+    pub fn is_update_step(&self, step: uint) -> bool {
+        self.interval > 0 && step % self.interval == 0
+    }
+
+    pub fn write_to<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        try!(write_uint(w, self.cells.len()));
+        for cell in self.cells.iter() {
+            try!(write_vec3(w, &cell.center));
+            try!(w.write_le_f32(cell.strength));
+        }
+
+        try!(write_uint(w, self.interval));
+        try!(w.write_le_f32(self.slab_pull));
+        w.write_le_f32(self.response)
+    }
+
+    pub fn read_from<R: Reader>(r: &mut R) -> IoResult<MantleConvection> {
+        let num_cells = try!(read_uint(r));
+        let mut cells = Vec::new();
+        for _ in range(0u, num_cells) {
+            cells.push(ConvectionCell {
+                center: try!(read_vec3(r)),
+                strength: try!(r.read_le_f32())
+            });
+        }
+
+        Ok(MantleConvection {
+            cells: cells,
+            interval: try!(read_uint(r)),
+            slab_pull: try!(r.read_le_f32()),
+            response: try!(r.read_le_f32())
+        })
+    }
+}
+
+/// Finds the rotation vector (Euler pole scaled by angular velocity) that
+/// best reproduces the given surface velocities of points on the unit
+/// sphere, in the least squares sense.
+pub fn fit_rotation(points: &[Vector3<f32>],
+                    velocities: &[Vector3<f32>]) -> Option<Vector3<f32>> {
+    // minimizing sum |w x p - v|^2 gives (sum (I - p p^T)) w = sum p x v
+    let mut m = [[0.0f32, ..3], ..3];
+    let mut rhs = Vector3::new(0.0f32, 0.0, 0.0);
+
+    for (p, v) in points.iter().zip(velocities.iter()) {
+        let p = p.normalize();
+        let c = [p.x, p.y, p.z];
+
+        for row in range(0u, 3) {
+            for col in range(0u, 3) {
+                let identity = if row == col { 1.0 } else { 0.0 };
+                m[col][row] += identity - c[row] * c[col];
+            }
+        }
+
+        rhs = rhs.add(&p.cross(v));
+    }
+
+    let mat = Matrix3::new(m[0][0], m[0][1], m[0][2],
+                           m[1][0], m[1][1], m[1][2],
+                           m[2][0], m[2][1], m[2][2]);
+
+    mat.invert().map(|inv| inv.mul_v(&rhs))
+}
+
+/// Direction of slab pull acting on a subducting point at `pos`, towards
+/// the overriding point at `nbr_pos`.
+pub fn slab_pull_direction(pos: &Vector3<f32>,
+                           nbr_pos: &Vector3<f32>) -> Vector3<f32> {
+    let dir = tangential(&nbr_pos.sub(pos), &pos.normalize());
+    if dir.length2() > 0.0 {
+        dir.normalize()
+    } else {
+        dir
+    }
+}
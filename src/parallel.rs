@@ -0,0 +1,51 @@
+use std::cmp;
+use std::os;
+use std::sync::Arc;
+use std::vec::Vec;
+
+/// Number of worker threads to use if not configured explicitly.
+pub fn default_num_threads() -> uint {
+    cmp::max(os::num_cpus(), 1)
+}
+
+/// Computes `f(data, i)` for every `i` in `[0, len)`, splitting the range
+/// into contiguous chunks processed by up to `num_threads` tasks.
+///
+/// Results are returned in index order, so the output does not depend on
+/// the number of threads or on task scheduling.
+pub fn map_indices<D: Send + Sync, T: Send>(data: Arc<D>,
+                                            len: uint,
+                                            num_threads: uint,
+                                            f: fn(&D, uint) -> T) -> Vec<T> {
+    if num_threads <= 1 || len < 2 {
+        return range(0u, len).map(|i| f(&*data, i)).collect();
+    }
+
+    let chunk_size = (len + num_threads - 1) / num_threads;
+    let num_chunks = (len + chunk_size - 1) / chunk_size;
+    let (tx, rx) = channel();
+
+    for chunk_idx in range(0u, num_chunks) {
+        let start = chunk_idx * chunk_size;
+        let end = cmp::min(start + chunk_size, len);
+        let tx = tx.clone();
+        let data = data.clone();
+
+        spawn(proc() {
+            let results: Vec<T> = range(start, end).map(|i| f(&*data, i)).collect();
+            tx.send((chunk_idx, results));
+        });
+    }
+
+    let mut chunks: Vec<Option<Vec<T>>> = Vec::from_fn(num_chunks, |_| None);
+    for _ in range(0u, num_chunks) {
+        let (chunk_idx, results) = rx.recv();
+        chunks[chunk_idx] = Some(results);
+    }
+
+    let mut ret = Vec::with_capacity(len);
+    for chunk in chunks.into_iter() {
+        ret.extend(chunk.unwrap().into_iter());
+    }
+    ret
+}
@@ -0,0 +1,392 @@
+extern crate cgmath;
+
+use std::vec::Vec;
+use std::num::{Float, FloatMath};
+use std::rand::Rng;
+use std::collections::BinaryHeap;
+use std::f32::consts::PI_2;
+
+use time;
+use cgmath::{EuclideanVector, Vector, Vector3};
+
+use plate_simulation::random_pole;
+use domain::{Topology, SphereMesh};
+
+include!("macros.rs")
+
+/// Splits points of a topology into plates. Returns point indices of each
+/// plate.
+pub trait PartitionStrategy<T: Topology> {
+    fn partition<R: Rng>(&self,
+                         rng: &mut R,
+                         topology: &T,
+                         num_plates: uint) -> Vec<Vec<uint>>;
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum PartitionKind {
+    FloodFill,
+    Voronoi,
+    WeightedFloodFill,
+    NoisyVoronoi
+}
+
+impl PartitionKind {
+    pub fn from_name(name: &str) -> Option<PartitionKind> {
+        match name {
+            "flood" => Some(PartitionKind::FloodFill),
+            "voronoi" => Some(PartitionKind::Voronoi),
+            "weighted" => Some(PartitionKind::WeightedFloodFill),
+            "noisy" => Some(PartitionKind::NoisyVoronoi),
+            _ => None
+        }
+    }
+}
+
+pub fn partition<R: Rng>(kind: PartitionKind,
+                         rng: &mut R,
+                         mesh: &SphereMesh,
+                         num_plates: uint) -> Vec<Vec<uint>> {
+    match kind {
+        PartitionKind::FloodFill =>
+            FloodFill.partition(rng, mesh, num_plates),
+        PartitionKind::Voronoi =>
+            Voronoi.partition(rng, mesh, num_plates),
+        PartitionKind::WeightedFloodFill =>
+            WeightedFloodFill { pareto_shape: 1.0 }.partition(rng, mesh, num_plates),
+        PartitionKind::NoisyVoronoi =>
+            NoisyVoronoi { amplitude: 0.3 }.partition(rng, mesh, num_plates),
+    }
+}
+
+/// Picks `num_plates` distinct random points.
+fn random_seeds<R: Rng>(rng: &mut R,
+                        num_points: uint,
+                        num_plates: uint) -> Vec<uint> {
+    let mut taken = Vec::from_elem(num_points, false);
+    let mut seeds = Vec::with_capacity(num_plates);
+
+    for _ in range(0u, num_plates) {
+        loop {
+            let idx = rng.gen_range(0u, num_points);
+
+            if !taken[idx] {
+                taken[idx] = true;
+                seeds.push(idx);
+                break
+            }
+        }
+    }
+
+    seeds
+}
+
+fn nearest_seed<T: Topology>(topology: &T,
+                              idx: uint,
+                              seeds: &Vec<uint>) -> uint {
+    let mut best_idx = 0u;
+    let mut best_dist = Float::infinity();
+
+    for i in range(0u, seeds.len()) {
+        let dist = topology.distance(idx, seeds[i]);
+        if dist < best_dist {
+            best_dist = dist;
+            best_idx = i;
+        }
+    }
+
+    best_idx
+}
+
+/// Breadth-first, round-robin growth from uniformly random seeds. Makes
+/// plates of roughly equal size.
+pub struct FloodFill;
+
+fn assign_neighbors(plate_points: &mut Vec<Vec<uint>>,
+                    new_frontier: &mut Vec<uint>,
+                    plate_id_for_verts: &mut Vec<int>,
+                    plate_idx: uint,
+                    nbr_indices: &[uint]) -> uint {
+    let mut num_assigned = 0u;
+
+    for &nbr_idx in nbr_indices.iter() {
+        if plate_id_for_verts[nbr_idx] == -1 {
+            plate_id_for_verts[nbr_idx] = plate_idx as int;
+            plate_points[plate_idx].push(nbr_idx);
+            new_frontier.push(nbr_idx);
+            num_assigned += 1;
+        }
+    }
+
+    num_assigned
+}
+
+fn flood_fill<T: Topology>(topology: &T,
+                           plate_id_for_verts: &mut Vec<int>,
+                           plate_points: &mut Vec<Vec<uint>>) {
+    let mut filled_points = plate_points.len();
+    let mut frontier_points = plate_points.clone();
+
+    while filled_points < topology.num_points() {
+        //println!("{} points to go", topology.num_points() - filled_points);
+
+        for plate_idx in range(0u, plate_points.len()) {
+            let mut new_frontier = Vec::new();
+
+            for &point_idx in frontier_points[plate_idx].iter() {
+                filled_points += assign_neighbors(plate_points, &mut new_frontier,
+                                                  plate_id_for_verts, plate_idx,
+                                                  topology.neighbors(point_idx));
+            }
+
+            frontier_points[plate_idx] = new_frontier;
+        }
+    }
+}
+
+impl<T: Topology> PartitionStrategy<T> for FloodFill {
+    fn partition<R: Rng>(&self,
+                         rng: &mut R,
+                         topology: &T,
+                         num_plates: uint) -> Vec<Vec<uint>> {
+        let mut plate_id_for_verts = Vec::from_elem(topology.num_points(), -1i);
+        let mut plate_points = Vec::with_capacity(num_plates);
+
+        for &idx in random_seeds(rng, topology.num_points(), num_plates).iter() {
+            plate_id_for_verts[idx] = plate_points.len() as int;
+            plate_points.push(vec![idx]);
+        }
+
+        time_it!("flood fill", 5.0f64, {
+            flood_fill(topology, &mut plate_id_for_verts, &mut plate_points);
+        });
+
+        plate_points
+    }
+}
+
+/// Assigns every point to the nearest of uniformly random seeds.
+pub struct Voronoi;
+
+impl<T: Topology> PartitionStrategy<T> for Voronoi {
+    fn partition<R: Rng>(&self,
+                         rng: &mut R,
+                         topology: &T,
+                         num_plates: uint) -> Vec<Vec<uint>> {
+        let seeds = random_seeds(rng, topology.num_points(), num_plates);
+        let mut plate_points = Vec::from_fn(num_plates, |_| Vec::new());
+
+        for i in range(0u, topology.num_points()) {
+            plate_points[nearest_seed(topology, i, &seeds)].push(i);
+        }
+
+        plate_points
+    }
+}
+
+/// Flood fill where every plate grows at its own, Pareto-distributed rate.
+/// Produces a few large plates and many small ones, similar to the
+/// power-law plate size distribution observed on Earth.
+pub struct WeightedFloodFill {
+    /// Shape parameter of the growth rate distribution. Lower values give
+    /// a more uneven size distribution.
+    pub pareto_shape: f32
+}
+
+/// Frontier entry ordered so that `BinaryHeap` pops the lowest arrival time
+/// first. Ties are broken by plate and point index to keep the result
+/// deterministic.
+struct Arrival {
+    time: f32,
+    plate_idx: uint,
+    point_idx: uint
+}
+
+impl PartialEq for Arrival {
+    fn eq(&self, other: &Arrival) -> bool {
+        self.cmp(other) == Equal
+    }
+}
+impl Eq for Arrival {}
+
+impl PartialOrd for Arrival {
+    fn partial_cmp(&self, other: &Arrival) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Arrival {
+    fn cmp(&self, other: &Arrival) -> Ordering {
+        if self.time < other.time {
+            Greater
+        } else if self.time > other.time {
+            Less
+        } else {
+            (other.plate_idx, other.point_idx).cmp(&(self.plate_idx, self.point_idx))
+        }
+    }
+}
+
+impl<T: Topology> PartitionStrategy<T> for WeightedFloodFill {
+    fn partition<R: Rng>(&self,
+                         rng: &mut R,
+                         topology: &T,
+                         num_plates: uint) -> Vec<Vec<uint>> {
+        let seeds = random_seeds(rng, topology.num_points(), num_plates);
+        let growth_rates: Vec<f32> = range(0u, num_plates).map(|_| {
+            let u = rng.gen_range(0.01f32, 1.0);
+            u.powf(-1.0 / self.pareto_shape)
+        }).collect();
+
+        let mut plate_id_for_verts = Vec::from_elem(topology.num_points(), -1i);
+        let mut plate_points = Vec::from_fn(num_plates, |_| Vec::new());
+        let mut frontier = BinaryHeap::new();
+
+        for plate_idx in range(0u, num_plates) {
+            frontier.push(Arrival { time: 0.0, plate_idx: plate_idx, point_idx: seeds[plate_idx] });
+        }
+
+        loop {
+            let arrival = match frontier.pop() {
+                Some(arrival) => arrival,
+                None => break
+            };
+
+            if plate_id_for_verts[arrival.point_idx] != -1 {
+                continue;
+            }
+            plate_id_for_verts[arrival.point_idx] = arrival.plate_idx as int;
+            plate_points[arrival.plate_idx].push(arrival.point_idx);
+
+            for &nbr_idx in topology.neighbors(arrival.point_idx).iter() {
+                if plate_id_for_verts[nbr_idx] == -1 {
+                    let dist = topology.distance(arrival.point_idx, nbr_idx);
+                    frontier.push(Arrival {
+                        time: arrival.time + dist / growth_rates[arrival.plate_idx],
+                        plate_idx: arrival.plate_idx,
+                        point_idx: nbr_idx
+                    });
+                }
+            }
+        }
+
+        plate_points
+    }
+}
+
+/// Voronoi partition computed on positions displaced by smooth noise,
+/// giving irregular, wiggly plate boundaries. Only defined on the sphere.
+pub struct NoisyVoronoi {
+    /// Maximum displacement of a point, relative to the sphere radius.
+    pub amplitude: f32
+}
+
+/// Sum of a few randomly oriented plane waves over the sphere.
+struct WaveNoise {
+    waves: Vec<(Vector3<f32>, f32, f32)>
+}
+
+impl WaveNoise {
+    fn new<R: Rng>(rng: &mut R) -> WaveNoise {
+        const NUM_WAVES: uint = 6;
+
+        WaveNoise {
+            waves: range(0u, NUM_WAVES).map(|i| {
+                let frequency = 2.0 * (i + 1) as f32;
+                (random_pole(rng), frequency, rng.gen_range(0.0f32, PI_2))
+            }).collect()
+        }
+    }
+
+    /// Noise value at `pos`, in range [-1, 1].
+    fn at(&self, pos: &Vector3<f32>) -> f32 {
+        let mut sum = 0.0f32;
+        let mut total_weight = 0.0f32;
+
+        for &(ref dir, frequency, phase) in self.waves.iter() {
+            let weight = 1.0 / frequency;
+            sum += weight * (frequency * dir.dot(pos) + phase).sin();
+            total_weight += weight;
+        }
+
+        sum / total_weight
+    }
+}
+
+impl PartitionStrategy<SphereMesh> for NoisyVoronoi {
+    fn partition<R: Rng>(&self,
+                         rng: &mut R,
+                         mesh: &SphereMesh,
+                         num_plates: uint) -> Vec<Vec<uint>> {
+        let noise = [WaveNoise::new(rng), WaveNoise::new(rng), WaveNoise::new(rng)];
+        let displaced = SphereMesh {
+            positions: mesh.positions.iter().map(|p| {
+                let offset = Vector3::new(noise[0].at(p), noise[1].at(p), noise[2].at(p));
+                p.add(&offset.mul_s(self.amplitude)).normalize()
+            }).collect(),
+            neighbors: mesh.neighbors.clone(),
+            edges: mesh.edges.clone()
+        };
+
+        Voronoi.partition(rng, &displaced, num_plates)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rand::Rng;
+
+    use torus::TorusGrid;
+
+    use super::{PartitionStrategy, FloodFill, Voronoi, WeightedFloodFill};
+
+    /// Returns given values, then zeros. Small values make `gen_range(0, n)`
+    /// return the value modulo n and float ranges return their low end.
+    struct Sequence {
+        values: Vec<u64>,
+        next: uint
+    }
+
+    impl Rng for Sequence {
+        fn next_u32(&mut self) -> u32 {
+            self.next_u64() as u32
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            let value = if self.next < self.values.len() { self.values[self.next] } else { 0 };
+            self.next += 1;
+            value
+        }
+    }
+
+    /// Partitions a 3x3 grid into two plates seeded at (0, 0) and (2, 2),
+    /// with point indices of each plate sorted.
+    fn partition_corners<P: PartitionStrategy<TorusGrid>>(strategy: &P) -> Vec<Vec<uint>> {
+        let mut rng = Sequence { values: vec![0, 8], next: 0 };
+        let mut plates = strategy.partition(&mut rng, &TorusGrid::new(3, 3), 2);
+        for plate in plates.iter_mut() {
+            plate.sort();
+        }
+        plates
+    }
+
+    // On the torus (2, 2) neighbors (0, 2) and (2, 0), so both seeds are
+    // equally close to those points as well as to (1, 1); the first seed
+    // wins every tie, leaving (2, 1), (1, 2) and (2, 2) to the second.
+
+    #[test]
+    fn flood_fill_on_grid() {
+        assert_eq!(partition_corners(&FloodFill), vec![vec![0u, 1, 2, 3, 4, 6], vec![5u, 7, 8]]);
+    }
+
+    #[test]
+    fn voronoi_on_grid() {
+        assert_eq!(partition_corners(&Voronoi), vec![vec![0u, 1, 2, 3, 4, 6], vec![5u, 7, 8]]);
+    }
+
+    #[test]
+    fn weighted_flood_fill_with_equal_rates_on_grid() {
+        let strategy = WeightedFloodFill { pareto_shape: 1.0 };
+        assert_eq!(partition_corners(&strategy), vec![vec![0u, 1, 2, 3, 4, 6], vec![5u, 7, 8]]);
+    }
+}
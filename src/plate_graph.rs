@@ -0,0 +1,115 @@
+use std::vec::Vec;
+use std::collections::TreeMap;
+use std::io::IoResult;
+
+use geo::angle_between;
+use plate_simulation::{BoundarySegment, BoundaryType};
+
+/// Pair of touching plates.
+#[deriving(Clone, Show)]
+pub struct PlateGraphEdge {
+    /// Plate indices, lower index first.
+    pub plates: [uint, ..2],
+    pub shared_length_km: f32,
+    /// Type covering most of the shared boundary length.
+    pub boundary_type: BoundaryType
+}
+
+/// Undirected graph of plates, with an edge between every pair of plates
+/// sharing a boundary.
+pub struct PlateGraph {
+    pub plate_sizes: Vec<uint>,
+    pub edges: Vec<PlateGraphEdge>
+}
+
+fn boundary_type_idx(boundary_type: &BoundaryType) -> uint {
+    match *boundary_type {
+        BoundaryType::Convergent => 0,
+        BoundaryType::Divergent => 1,
+        BoundaryType::Transform => 2
+    }
+}
+
+impl PlateGraph {
+    pub fn from_segments(segments: &Vec<BoundarySegment>,
+                         plate_sizes: Vec<uint>,
+                         planet_radius_km: f32) -> PlateGraph {
+        // boundary length of every type, per plate pair
+        let mut lengths: TreeMap<(uint, uint), [f32, ..3]> = TreeMap::new();
+
+        for segment in segments.iter() {
+            let key = (segment.plate_indices[0], segment.plate_indices[1]);
+            let mut sums = match lengths.get(&key) {
+                Some(&sums) => sums,
+                None => [0.0f32, ..3]
+            };
+            sums[boundary_type_idx(&segment.boundary_type)] +=
+                angle_between(&segment.endpoints[0], &segment.endpoints[1]) * planet_radius_km;
+            lengths.insert(key, sums);
+        }
+
+        let edges = lengths.iter().map(|(&(a, b), sums)| {
+            let types = [BoundaryType::Convergent, BoundaryType::Divergent, BoundaryType::Transform];
+            let mut dominant = 0u;
+            for i in range(1u, 3) {
+                if sums[i] > sums[dominant] {
+                    dominant = i;
+                }
+            }
+
+            PlateGraphEdge {
+                plates: [a, b],
+                shared_length_km: sums[0] + sums[1] + sums[2],
+                boundary_type: types[dominant].clone()
+            }
+        }).collect();
+
+        PlateGraph {
+            plate_sizes: plate_sizes,
+            edges: edges
+        }
+    }
+
+    /// Indices of plates touching `plate_idx`.
+    pub fn neighbors(&self, plate_idx: uint) -> Vec<uint> {
+        self.edges.iter().filter_map(|e| {
+            if e.plates[0] == plate_idx {
+                Some(e.plates[1])
+            } else if e.plates[1] == plate_idx {
+                Some(e.plates[0])
+            } else {
+                None
+            }
+        }).collect()
+    }
+
+    /// Writes the graph in Graphviz DOT format. Edges are colored by
+    /// boundary type and their width grows with the shared boundary length.
+    pub fn write_dot<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        try!(writeln!(w, "graph plates {{"));
+
+        for plate_idx in range(0u, self.plate_sizes.len()) {
+            try!(writeln!(w, "    {} [label=\"{}\\n{} points\"];",
+                          plate_idx, plate_idx, self.plate_sizes[plate_idx]));
+        }
+
+        let max_length = self.edges.iter().fold(0.0f32, |a, e| a.max(e.shared_length_km));
+        for e in self.edges.iter() {
+            let color = match e.boundary_type {
+                BoundaryType::Convergent => "red",
+                BoundaryType::Divergent => "blue",
+                BoundaryType::Transform => "green"
+            };
+            let width = if max_length > 0.0 {
+                1.0 + 4.0 * e.shared_length_km / max_length
+            } else {
+                1.0
+            };
+
+            try!(writeln!(w, "    {} -- {} [label=\"{:.0} km\", color={}, penwidth={:.2}];",
+                          e.plates[0], e.plates[1], e.shared_length_km, color, width));
+        }
+
+        writeln!(w, "}}")
+    }
+}
@@ -0,0 +1,262 @@
+extern crate cgmath;
+extern crate serialize;
+
+use std::vec::Vec;
+use std::num::{Float, FloatMath};
+use std::io::File;
+use std::f32::consts::PI;
+
+use cgmath::{EuclideanVector, Vector, Vector3};
+use serialize::json;
+
+use geo::from_lat_lon_deg;
+use plate_simulation::CrustType;
+
+/// Hand-authored description of a single plate. Its area is given either
+/// by a polygon, by seed points, or both: points inside a polygon belong
+/// to its plate, all other points go to the plate with the nearest seed.
+#[deriving(Clone, Show, Decodable)]
+pub struct PlateDesc {
+    /// Seed points, as [latitude, longitude] pairs in degrees.
+    pub seeds: Option<Vec<Vec<f32>>>,
+    /// Boundary polygon vertices, as [latitude, longitude] pairs in
+    /// degrees, in any winding order.
+    pub polygon: Option<Vec<Vec<f32>>>,
+    /// Euler pole as [latitude, longitude] in degrees.
+    pub euler_pole: Vec<f32>,
+    /// Angular velocity around the Euler pole, in degrees per Myr.
+    pub angular_velocity: f32,
+    pub height: f32,
+    /// "continental" or "oceanic". Derived from height if missing.
+    pub crust: Option<String>
+}
+
+/// Plate configuration loaded from a JSON file, e.g.:
+///
+/// ```json
+/// { "plates": [ { "seeds": [[45, 10]], "euler_pole": [60, -90],
+///                 "angular_velocity": 0.5, "height": 1.01,
+///                 "crust": "continental" } ] }
+/// ```
+#[deriving(Clone, Show, Decodable)]
+pub struct PlateLayout {
+    pub plates: Vec<PlateDesc>
+}
+
+fn parse_lat_lon(pair: &Vec<f32>) -> Result<Vector3<f32>, String> {
+    if pair.len() != 2 {
+        return Err(format!("expected [latitude, longitude], got {}", pair));
+    }
+    Ok(from_lat_lon_deg(pair[0], pair[1]))
+}
+
+fn parse_lat_lon_list(pairs: &Option<Vec<Vec<f32>>>) -> Result<Vec<Vector3<f32>>, String> {
+    let mut ret = Vec::new();
+    match *pairs {
+        Some(ref pairs) => for pair in pairs.iter() {
+            ret.push(try!(parse_lat_lon(pair)));
+        },
+        None => {}
+    }
+    Ok(ret)
+}
+
+/// Checks whether `pos` lies inside a closed spherical polygon by summing
+/// the angles its edges subtend as seen from `pos`. The winding number is
+/// the same at the antipode of every inner point, so polygons must fit in
+/// a hemisphere around every point inside them: points 90 degrees or more
+/// away from any vertex are outside.
+fn inside_polygon(pos: &Vector3<f32>,
+                  polygon: &Vec<Vector3<f32>>) -> bool {
+    if polygon.iter().any(|v| pos.dot(v) <= 0.0) {
+        return false;
+    }
+
+    let tangent = |v: &Vector3<f32>| v.sub(&pos.mul_s(v.dot(pos)));
+    let mut winding = 0.0f32;
+
+    for i in range(0u, polygon.len()) {
+        let a = tangent(&polygon[i]);
+        let b = tangent(&polygon[(i + 1) % polygon.len()]);
+        winding += pos.dot(&a.cross(&b)).atan2(a.dot(&b));
+    }
+
+    // +-2pi inside, 0 outside
+    winding.abs() > PI
+}
+
+impl PlateDesc {
+    pub fn euler_pole_vec(&self) -> Result<Vector3<f32>, String> {
+        parse_lat_lon(&self.euler_pole)
+    }
+
+    pub fn crust_type(&self) -> Result<Option<CrustType>, String> {
+        match self.crust {
+            Some(ref name) => match name.as_slice() {
+                "continental" => Ok(Some(CrustType::Continental)),
+                "oceanic" => Ok(Some(CrustType::Oceanic)),
+                _ => Err(format!("unknown crust type: {}", name))
+            },
+            None => Ok(None)
+        }
+    }
+}
+
+impl PlateLayout {
+    pub fn load(path: &Path) -> Result<PlateLayout, String> {
+        let text = match File::open(path).read_to_string() {
+            Ok(text) => text,
+            Err(e) => return Err(format!("cannot read {}: {}", path.display(), e))
+        };
+
+        match json::decode::<PlateLayout>(text.as_slice()) {
+            Ok(layout) => Ok(layout),
+            Err(e) => Err(format!("invalid plate layout {}: {}", path.display(), e))
+        }
+    }
+
+    /// Splits points into plates. Returns point indices of every plate,
+    /// in the same order as `plates`.
+    pub fn assign_points(&self,
+                         positions: &Vec<Vector3<f32>>) -> Result<Vec<Vec<uint>>, String> {
+        let mut polygons = Vec::with_capacity(self.plates.len());
+        let mut seeds = Vec::new();
+
+        for plate_idx in range(0u, self.plates.len()) {
+            let plate = &self.plates[plate_idx];
+            polygons.push(try!(parse_lat_lon_list(&plate.polygon)));
+            for seed in try!(parse_lat_lon_list(&plate.seeds)).into_iter() {
+                seeds.push((seed, plate_idx));
+            }
+        }
+
+        let mut plate_points = Vec::from_fn(self.plates.len(), |_| Vec::new());
+
+        for point_idx in range(0u, positions.len()) {
+            let pos = positions[point_idx].normalize();
+
+            let by_polygon = range(0u, polygons.len()).find(|&i| {
+                polygons[i].len() >= 3 && inside_polygon(&pos, &polygons[i])
+            });
+
+            let plate_idx = match by_polygon {
+                Some(plate_idx) => plate_idx,
+                None => {
+                    let mut best = None;
+                    let mut best_dot = -2.0f32;
+                    for &(ref seed, plate_idx) in seeds.iter() {
+                        let dot = seed.dot(&pos);
+                        if dot > best_dot {
+                            best_dot = dot;
+                            best = Some(plate_idx);
+                        }
+                    }
+
+                    match best {
+                        Some(plate_idx) => plate_idx,
+                        None => return Err(format!("point {} is not covered by any plate polygon \
+                                                    and there are no seed points", point_idx))
+                    }
+                }
+            };
+
+            plate_points[plate_idx].push(point_idx);
+        }
+
+        for plate_idx in range(0u, plate_points.len()) {
+            if plate_points[plate_idx].is_empty() {
+                return Err(format!("plate {} does not contain any points", plate_idx));
+            }
+        }
+
+        Ok(plate_points)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use cgmath::Vector3;
+
+    use geo::from_lat_lon_deg;
+
+    use super::{PlateDesc, PlateLayout, inside_polygon};
+
+    /// Corners of a square 20 degrees wide centered at latitude and
+    /// longitude 0, counterclockwise seen from outside.
+    fn square() -> Vec<Vec<f32>> {
+        vec![vec![-10.0, -10.0], vec![-10.0, 10.0], vec![10.0, 10.0], vec![10.0, -10.0]]
+    }
+
+    fn to_vectors(pairs: &Vec<Vec<f32>>) -> Vec<Vector3<f32>> {
+        pairs.iter().map(|p| from_lat_lon_deg(p[0], p[1])).collect()
+    }
+
+    fn plate(seeds: Option<Vec<Vec<f32>>>, polygon: Option<Vec<Vec<f32>>>) -> PlateDesc {
+        PlateDesc {
+            seeds: seeds,
+            polygon: polygon,
+            euler_pole: vec![90.0, 0.0],
+            angular_velocity: 0.5,
+            height: 1.0,
+            crust: None
+        }
+    }
+
+    #[test]
+    fn point_inside_square() {
+        let ccw = to_vectors(&square());
+        let mut cw = ccw.clone();
+        cw.reverse();
+
+        for polygon in [ccw, cw].iter() {
+            assert!(inside_polygon(&from_lat_lon_deg(0.0, 0.0), polygon));
+            assert!(inside_polygon(&from_lat_lon_deg(5.0, -8.0), polygon));
+            assert!(!inside_polygon(&from_lat_lon_deg(0.0, 20.0), polygon));
+            assert!(!inside_polygon(&from_lat_lon_deg(-15.0, 0.0), polygon));
+        }
+    }
+
+    #[test]
+    fn antipode_is_outside() {
+        let polygon = to_vectors(&square());
+
+        assert!(!inside_polygon(&from_lat_lon_deg(0.0, 180.0), &polygon));
+        assert!(!inside_polygon(&from_lat_lon_deg(-5.0, 172.0), &polygon));
+    }
+
+    #[test]
+    fn polygons_take_precedence_over_seeds() {
+        let layout = PlateLayout {
+            plates: vec![plate(None, Some(square())),
+                         plate(Some(vec![vec![0.0, 5.0], vec![0.0, 90.0]]), None)]
+        };
+        // the seed at (0, 5) lies inside the polygon of plate 0
+        let positions = to_vectors(&vec![vec![0.0, 0.0], vec![0.0, 8.0], vec![0.0, 30.0],
+                                         vec![0.0, 180.0], vec![60.0, 0.0]]);
+
+        assert_eq!(layout.assign_points(&positions),
+                   Ok(vec![vec![0u, 1], vec![2u, 3, 4]]));
+    }
+
+    #[test]
+    fn antipode_of_polygon_goes_to_nearest_seed() {
+        let layout = PlateLayout {
+            plates: vec![plate(None, Some(square())),
+                         plate(Some(vec![vec![0.0, 100.0]]), None),
+                         plate(Some(vec![vec![0.0, -100.0]]), None)]
+        };
+        let positions = to_vectors(&vec![vec![0.0, 0.0], vec![0.0, 175.0], vec![0.0, -175.0]]);
+
+        assert_eq!(layout.assign_points(&positions),
+                   Ok(vec![vec![0u], vec![1u], vec![2u]]));
+    }
+
+    #[test]
+    fn uncovered_points_without_seeds_are_an_error() {
+        let layout = PlateLayout { plates: vec![plate(None, Some(square()))] };
+        let positions = to_vectors(&vec![vec![0.0, 0.0], vec![0.0, 180.0]]);
+
+        assert!(layout.assign_points(&positions).is_err());
+    }
+}
@@ -2,24 +2,149 @@ extern crate cgmath;
 extern crate gfx;
 
 use std::vec::Vec;
-use std::num::FloatMath;
+use std::num::{Float, FloatMath};
 use std::rand::Rng;
+use std::collections::TreeMap;
+use std::f32::consts::{PI, PI_2};
+use std::io::IoResult;
+use std::sync::Arc;
 
 use time;
-use cgmath::{EuclideanVector, Vector, Vector3, Basis3, Rotation, Rotation3, Rad, rad, FixedArray};
+use cgmath::{EuclideanVector, Vector, Vector3, Basis3, Rotation, Rotation3, Rad, rad, deg, ToRad, FixedArray};
 use gfx::batch::Context;
 use gfx::{GlDevice, Device, DeviceHelper, ToSlice};
 
 use polyhedron::{Edge, Polyhedron};
+use partition;
+use parallel;
+use geo::angle_between;
+use plate_graph::PlateGraph;
+use plate_layout::PlateLayout;
+use reconstruction::{RotationHistory, StageRotation};
+use events::{Event, EventKind, EventLog, FaultStress};
+use partition::PartitionKind;
+use domain::{Topology, PlateDomain, SphereMesh, boundary_edges, head_on_collisions,
+             track_collisions};
+use mantle_convection::{MantleConvection, fit_rotation, slab_pull_direction};
+use hotspot::{Hotspot, VolcanicRecord, plume_radius};
+use checkpoint::{invalid_data, write_uint, read_uint, write_bool, read_bool,
+                 write_vec3, read_vec3, write_uint_vec, read_uint_vec};
 use rendering;
-use rendering::{PolyhedronBatch, Vertex, color_by_index};
+use rendering::{PolyhedronBatch, Vertex, color_by_index, color_for_hue};
 
 include!("macros.rs")
 
-struct PlatePoint {
+#[deriving(Clone, PartialEq, Show)]
+pub enum CrustType {
+    Oceanic,
+    Continental
+}
+
+pub const EARTH_RADIUS_KM: f32 = 6371.0;
+
+/// Typical range of present-day plate surface velocities on Earth, in cm/yr.
+pub const EARTH_PLATE_VELOCITY_CM_PER_YR: (f32, f32) = (1.0, 10.0);
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum BoundaryType {
+    Convergent,
+    Divergent,
+    Transform
+}
+
+impl BoundaryType {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            BoundaryType::Convergent => "convergent",
+            BoundaryType::Divergent => "divergent",
+            BoundaryType::Transform => "transform"
+        }
+    }
+
+    pub fn color(&self) -> [f32, ..4] {
+        match *self {
+            BoundaryType::Convergent => color_for_hue(0.0),
+            BoundaryType::Divergent => color_for_hue(4.0),
+            BoundaryType::Transform => color_for_hue(2.0)
+        }
+    }
+}
+
+/// Piece of a boundary between two plates, crossing a single mesh edge.
+#[deriving(Clone, Show)]
+pub struct BoundarySegment {
+    pub plate_indices: [uint, ..2],
+    /// Points on either side of the boundary, in the same order as
+    /// `plate_indices`.
+    pub vertex_indices: [uint, ..2],
+    /// Ends of the segment, at the centers of faces adjacent to the
+    /// crossed edge.
+    pub endpoints: [Vector3<f32>, ..2],
+    pub boundary_type: BoundaryType,
+    /// Speed at which the two sides approach each other, in cm/yr.
+    /// Negative if they move apart.
+    pub closing_speed: f32,
+    /// Total relative speed of the two sides, in cm/yr.
+    pub relative_speed: f32
+}
+
+/// Boundaries with closing speed below this fraction of the relative
+/// speed are classified as transform.
+const TRANSFORM_RATIO: f32 = 0.5;
+
+pub fn classify_boundary(closing_speed: f32,
+                     relative_speed: f32) -> BoundaryType {
+    if closing_speed.abs() < relative_speed * TRANSFORM_RATIO {
+        BoundaryType::Transform
+    } else if closing_speed > 0.0 {
+        BoundaryType::Convergent
+    } else {
+        BoundaryType::Divergent
+    }
+}
+
+/// Physical scale of the simulation.
+pub struct PlateSimParams {
+    pub num_plates: uint,
+    /// Planet radius, in kilometers.
+    pub planet_radius_km: f32,
+    /// Time covered by a single simulation step, in millions of years.
+    pub step_myr: f32,
+    /// Number of steps between plate velocity updates driven by mantle
+    /// convection. 0 keeps the initial random velocities.
+    pub convection_interval: uint,
+    pub partition: PartitionKind,
+    pub num_hotspots: uint,
+    /// Maximum uplift at a hotspot center, in height units per Myr.
+    pub hotspot_strength: f32,
+    pub num_threads: uint
+}
+
+impl PlateSimParams {
+    pub fn new(num_plates: uint) -> PlateSimParams {
+        PlateSimParams {
+            num_plates: num_plates,
+            planet_radius_km: EARTH_RADIUS_KM,
+            step_myr: 5.0,
+            convection_interval: 0,
+            partition: PartitionKind::FloodFill,
+            num_hotspots: 0,
+            hotspot_strength: 0.0005,
+            num_threads: 1
+        }
+    }
+}
+
+pub struct PlatePoint {
     pub pos: Vector3<f32>,
     nbr_indices: Vec<uint>,
-    pub speed: Rad<f32>
+    /// Angular velocity around the plate's Euler pole, in degrees per Myr.
+    pub angular_velocity: f32,
+    pub plate_idx: uint,
+    pub crust: CrustType,
+    /// Elevation added by hotspot volcanism.
+    pub volcanic_height: f32,
+    pub volcanism: Option<VolcanicRecord>
 }
 
 impl PlatePoint {
@@ -28,27 +153,97 @@ impl PlatePoint {
         PlatePoint {
             pos: *pos,
             nbr_indices: nbr_indices,
-            speed: rad(1.0)
+            angular_velocity: 0.0,
+            plate_idx: 0,
+            crust: CrustType::Oceanic,
+            volcanic_height: 0.0,
+            volcanism: None
         }
     }
 
-    fn move_around(&mut self, move_axis: &Vector3<f32>) {
-        let rot: Basis3<f32> = Rotation3::from_axis_angle(move_axis, self.speed);
+    fn step_angle(&self, step_myr: f32) -> Rad<f32> {
+        deg(self.angular_velocity * step_myr).to_rad()
+    }
+
+    fn move_around(&mut self, euler_pole: &Vector3<f32>, step_myr: f32) {
+        let rot: Basis3<f32> = Rotation3::from_axis_angle(euler_pole, self.step_angle(step_myr));
         self.pos = rot.rotate_vector(&self.pos);
     }
+
+    fn write_to<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        try!(write_vec3(w, &self.pos));
+        try!(write_uint_vec(w, &self.nbr_indices));
+        try!(w.write_le_f32(self.angular_velocity));
+        try!(write_uint(w, self.plate_idx));
+        try!(write_bool(w, self.crust == CrustType::Continental));
+        try!(w.write_le_f32(self.volcanic_height));
+
+        match self.volcanism {
+            Some(ref record) => {
+                try!(write_bool(w, true));
+                record.write_to(w)
+            },
+            None => write_bool(w, false)
+        }
+    }
+
+    fn read_from<R: Reader>(r: &mut R) -> IoResult<PlatePoint> {
+        let pos = try!(read_vec3(r));
+        let nbr_indices = try!(read_uint_vec(r));
+        let angular_velocity = try!(r.read_le_f32());
+        let plate_idx = try!(read_uint(r));
+        let crust = if try!(read_bool(r)) {
+            CrustType::Continental
+        } else {
+            CrustType::Oceanic
+        };
+        let volcanic_height = try!(r.read_le_f32());
+        let volcanism = if try!(read_bool(r)) {
+            Some(try!(VolcanicRecord::read_from(r)))
+        } else {
+            None
+        };
+
+        Ok(PlatePoint {
+            pos: pos,
+            nbr_indices: nbr_indices,
+            angular_velocity: angular_velocity,
+            plate_idx: plate_idx,
+            crust: crust,
+            volcanic_height: volcanic_height,
+            volcanism: volcanism
+        })
+    }
 }
 
-struct Plate {
+pub struct Plate {
     pub vertex_indices: Vec<uint>,
-    pub move_axis: Vector3<f32>,
-    pub move_speed: Rad<f32>,
+    /// Unit vector pointing at the plate's Euler pole.
+    pub euler_pole: Vector3<f32>,
+    /// Angular velocity around `euler_pole`, in degrees per Myr.
+    pub angular_velocity: f32,
     pub height: f32
 }
 
-fn random_axis<R: Rng>(rng: &mut R) -> Vector3<f32> {
-    Vector3::new(rng.gen_range(0.0001f32, 1.0),
-                 rng.gen_range(0.0001f32, 1.0),
-                 rng.gen_range(0.0001f32, 1.0)).normalize()
+/// Samples a point uniformly distributed over the unit sphere.
+pub fn random_pole<R: Rng>(rng: &mut R) -> Vector3<f32> {
+    let z = rng.gen_range(-1.0f32, 1.0);
+    let (sin_phi, cos_phi) = rng.gen_range(0.0f32, PI_2).sin_cos();
+    let r = (1.0 - z * z).sqrt();
+
+    Vector3::new(r * cos_phi, r * sin_phi, z)
+}
+
+/// Converts an angular velocity in degrees per Myr into the speed of
+/// a point `dist_from_pole` radians away from the Euler pole, in cm/yr.
+pub fn surface_velocity_cm_per_yr(angular_velocity: f32,
+                                  dist_from_pole: f32,
+                                  planet_radius_km: f32) -> f32 {
+    const CM_PER_KM: f32 = 100000.0;
+    const YR_PER_MYR: f32 = 1000000.0;
+
+    let rad_per_yr = angular_velocity * PI / 180.0 / YR_PER_MYR;
+    rad_per_yr * dist_from_pole.sin() * planet_radius_km * CM_PER_KM
 }
 
 fn make_vertex(pos: &Vector3<f32>,
@@ -62,13 +257,13 @@ fn make_vertex(pos: &Vector3<f32>,
 
 impl Plate {
     fn new(vertex_indices: Vec<uint>,
-           move_axis: &Vector3<f32>,
-           move_speed: Rad<f32>,
+           euler_pole: &Vector3<f32>,
+           angular_velocity: f32,
            height: f32) -> Plate {
         Plate {
             vertex_indices: vertex_indices,
-            move_axis: *move_axis,
-            move_speed: move_speed,
+            euler_pole: *euler_pole,
+            angular_velocity: angular_velocity,
             height: height
         }
     }
@@ -76,18 +271,57 @@ impl Plate {
     fn from_points<R: Rng>(rng: &mut R,
                            vertex_indices: Vec<uint>) -> Plate {
         const HEIGHT_DEV: f32 = 0.02;
+        // present-day Earth plates rotate at roughly 0.1-1.5 deg/Myr
+        const MIN_ANGULAR_VELOCITY: f32 = 0.1;
+        const MAX_ANGULAR_VELOCITY: f32 = 1.5;
 
         Plate::new(vertex_indices,
-                   &random_axis(rng),
-                   rad(rng.gen_range(0.01f32, 0.1)),
+                   &random_pole(rng),
+                   rng.gen_range(MIN_ANGULAR_VELOCITY, MAX_ANGULAR_VELOCITY),
                    rng.gen_range(1.0 - HEIGHT_DEV, 1.0 + HEIGHT_DEV))
     }
 
-    fn simulate(&self, vertices: &mut Vec<PlatePoint>) {
+    fn simulate(&self, vertices: &mut Vec<PlatePoint>, step_myr: f32) {
         for &idx in self.vertex_indices.iter() {
-            vertices[idx].move_around(&self.move_axis);
+            vertices[idx].move_around(&self.euler_pole, step_myr);
         }
     }
+
+    fn initial_crust(&self) -> CrustType {
+        if self.height > 1.0 {
+            CrustType::Continental
+        } else {
+            CrustType::Oceanic
+        }
+    }
+
+    fn is_continental(&self, vertices: &Vec<PlatePoint>) -> bool {
+        let continental = self.vertex_indices.iter()
+                              .filter(|&&idx| vertices[idx].crust == CrustType::Continental)
+                              .count();
+        continental * 2 > self.vertex_indices.len()
+    }
+
+    /// Euler pole scaled by the angular velocity, in degrees per Myr.
+    fn rotation_vector(&self) -> Vector3<f32> {
+        self.euler_pole.mul_s(self.angular_velocity)
+    }
+
+    fn write_to<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        try!(write_uint_vec(w, &self.vertex_indices));
+        try!(write_vec3(w, &self.euler_pole));
+        try!(w.write_le_f32(self.angular_velocity));
+        w.write_le_f32(self.height)
+    }
+
+    fn read_from<R: Reader>(r: &mut R) -> IoResult<Plate> {
+        Ok(Plate {
+            vertex_indices: try!(read_uint_vec(r)),
+            euler_pole: try!(read_vec3(r)),
+            angular_velocity: try!(r.read_le_f32()),
+            height: try!(r.read_le_f32())
+        })
+    }
 }
 
 fn get_nbr_idx(edge: &Edge, vert_idx: uint) -> uint {
@@ -98,158 +332,992 @@ fn get_nbr_idx(edge: &Edge, vert_idx: uint) -> uint {
     }
 }
 
-fn assign_neighbors(plate_points: &mut Vec<Vec<uint>>,
-                    new_frontier: &mut Vec<uint>,
-                    plate_id_for_verts: &mut Vec<int>,
-                    plate_idx: uint,
-                    nbr_indices: &Vec<uint>) -> uint {
-    let mut num_assigned = 0u;
+pub struct PlateSimulation {
+    initial_distance: f32,
+    planet_radius_km: f32,
+    step_myr: f32,
+    pub verts: Vec<PlatePoint>,
+    plates: Vec<Plate>,
+    collision_steps: TreeMap<(uint, uint), uint>,
+    edges: Vec<Edge>,
+    faces: Vec<[uint, ..3]>,
+    step: uint,
+    convection: Option<MantleConvection>,
+    hotspots: Vec<Hotspot>,
+    history: RotationHistory,
+    fault_stress: FaultStress,
+    events: EventLog,
+    num_threads: uint
+}
+
+fn make_plate_points(poly: &Polyhedron) -> Vec<PlatePoint> {
+    let mut verts = Vec::with_capacity(poly.vertices.len());
 
-    for &nbr_idx in nbr_indices.iter() {
-        if plate_id_for_verts[nbr_idx] == -1 {
-            plate_id_for_verts[nbr_idx] = plate_idx as int;
-            plate_points[plate_idx].push(nbr_idx);
-            new_frontier.push(nbr_idx);
-            num_assigned += 1;
-        }
+    for vert_idx in range(0u, poly.vertices.len()) {
+        let vert = &poly.vertices[vert_idx];
+        let nbr_indices = vert.edge_indices.iter()
+                              .map(|&i| get_nbr_idx(&poly.edges[i], vert_idx))
+                              .collect();
+        verts.push(PlatePoint::new(&vert.pos, nbr_indices));
     }
 
-    num_assigned
+    verts
 }
 
-fn flood_fill(verts: &Vec<PlatePoint>,
-              plate_id_for_verts: &mut Vec<int>,
-              plate_points: &mut Vec<Vec<uint>>) {
-    let mut filled_points = plate_points.len();
-    let mut frontier_points = plate_points.clone();
-
-    while filled_points < verts.len() {
-        //println!("{} points to go", verts.len() - filled_points);
+fn sphere_mesh(poly: &Polyhedron,
+               verts: &Vec<PlatePoint>) -> SphereMesh {
+    SphereMesh {
+        positions: verts.iter().map(|v| v.pos).collect(),
+        neighbors: verts.iter().map(|v| v.nbr_indices.clone()).collect(),
+        edges: poly.edges.iter().map(|e| e.vertex_indices).collect()
+    }
+}
 
-        for plate_idx in range(0u, plate_points.len()) {
-            let mut new_frontier = Vec::new();
+fn avg_distance(positions: &Vec<Vector3<f32>>,
+                idx: uint) -> f32 {
+    const DOT_THRESHOLD: f32 = 0.5;
 
-            for &point_idx in frontier_points[plate_idx].iter() {
-                filled_points += assign_neighbors(plate_points, &mut new_frontier,
-                                                  plate_id_for_verts, plate_idx,
-                                                  &verts[point_idx].nbr_indices);
-            }
+    let pos = &positions[idx];
+    let mut sum = 0.0f32;
 
-            frontier_points[plate_idx] = new_frontier;
-        }
+    for pos2 in positions.iter() {
+        sum += pos.dot(pos2).max(DOT_THRESHOLD);
     }
+
+    sum -= positions.len() as f32 * DOT_THRESHOLD;
+    sum / positions.len() as f32
 }
 
-fn random_partition<R: Rng>(rng: &mut R,
-                            verts: &Vec<PlatePoint>,
-                            num_plates: uint) -> Vec<Plate> {
-    let mut plate_id_for_verts = Vec::from_elem(verts.len(), -1i);
-    let mut plate_points = Vec::with_capacity(num_plates);
+fn get_edge_length(poly: &Polyhedron) -> f32 {
+    let edge = &poly.edges[0];
+    let verts = [&poly.vertices[edge.vertex_indices[0]],
+                 &poly.vertices[edge.vertex_indices[1]]];
+    let diff = verts[0].pos.sub(&verts[1].pos);
+    diff.length()
+}
 
-    for plate_idx in range(0u, num_plates) {
-        //println!("finding origin of plate {}/{} ({} verts total)", plate_idx, num_plates, verts.len());
-        loop {
-            let idx = rng.gen_range(0u, verts.len());
+impl Topology for PlateSimulation {
+    fn num_points(&self) -> uint {
+        self.verts.len()
+    }
 
-            if plate_id_for_verts[idx] == -1 {
-                plate_id_for_verts[idx] = plate_idx as int;
+    fn neighbors(&self, idx: uint) -> &[uint] {
+        self.verts[idx].nbr_indices.as_slice()
+    }
 
-                let plate_idx = plate_points.len();
-                plate_points.push(Vec::new());
-                plate_points[plate_idx].push(idx);
+    fn distance(&self, a: uint, b: uint) -> f32 {
+        self.verts[a].pos.sub(&self.verts[b].pos).length()
+    }
 
-                break
-            }
-        }
+    fn edge_length(&self) -> f32 {
+        self.initial_distance
     }
 
-    time_it!("flood fill", 5.0f64, {
-        flood_fill(verts, &mut plate_id_for_verts, &mut plate_points);
-    });
+    fn num_edges(&self) -> uint {
+        self.edges.len()
+    }
 
-    plate_points.iter().map(|points| Plate::from_points(rng, points.clone())).collect()
+    fn edge(&self, edge_idx: uint) -> [uint, ..2] {
+        self.edges[edge_idx].vertex_indices
+    }
 }
 
-pub struct PlateSimulation {
-    initial_distance: f32,
-    pub verts: Vec<PlatePoint>,
-    plates: Vec<Plate>
-}
+impl PlateDomain for PlateSimulation {
+    fn plate_of(&self, idx: uint) -> uint {
+        self.verts[idx].plate_idx
+    }
 
-fn get_edge_length(poly: &Polyhedron) -> f32 {
-    let edge = &poly.edges[0];
-    let verts = [&poly.vertices[edge.vertex_indices[0]],
-                 &poly.vertices[edge.vertex_indices[1]]];
-    let diff = verts[0].pos.sub(&verts[1].pos);
-    diff.length()
+    /// Speeds in cm/yr.
+    fn relative_motion(&self, a: uint, b: uint) -> Option<(f32, f32)> {
+        // rad/Myr on a unit sphere -> cm/yr
+        let to_cm_per_yr = self.planet_radius_km * 0.1;
+
+        let dir = self.verts[b].pos.sub(&self.verts[a].pos);
+        if dir.length2() == 0.0 {
+            return None;
+        }
+
+        let rel = self.point_velocity(a).sub(&self.point_velocity(b));
+        Some((rel.dot(&dir.normalize()) * to_cm_per_yr, rel.length() * to_cm_per_yr))
+    }
 }
 
 impl PlateSimulation {
     pub fn new<R: Rng>(poly: &Polyhedron,
-                       num_plates: uint,
+                       params: &PlateSimParams,
                        rng: &mut R) -> PlateSimulation {
+        let num_plates = params.num_plates;
         if poly.faces.len() < num_plates {
             panic_bt!("cannot split {} faces into {} plates", poly.faces.len(), num_plates);
         }
 
         println!("splitting world into {} plates", num_plates);
-        let mut verts = Vec::with_capacity(poly.vertices.len());
+        let verts = make_plate_points(poly);
 
-        for vert_idx in range(0u, poly.vertices.len()) {
-            let vert = &poly.vertices[vert_idx];
-            let nbr_indices = vert.edge_indices.iter()
-                                  .map(|&i| get_nbr_idx(&poly.edges[i], vert_idx))
-                                  .collect();
-            verts.push(PlatePoint::new(&vert.pos, nbr_indices));
+        let mesh = sphere_mesh(poly, &verts);
+        let plates: Vec<Plate> =
+            partition::partition(params.partition, rng, &mesh, num_plates)
+                .into_iter()
+                .map(|points| Plate::from_points(rng, points))
+                .collect();
+        let crusts = plates.iter().map(|p| p.initial_crust()).collect();
+
+        PlateSimulation::from_plates(poly, params, verts, plates, crusts, rng)
+    }
+
+    /// Creates a simulation with plates given by a hand-authored layout
+    /// instead of a random partition.
+    pub fn from_layout<R: Rng>(poly: &Polyhedron,
+                               params: &PlateSimParams,
+                               layout: &PlateLayout,
+                               rng: &mut R) -> Result<PlateSimulation, String> {
+        println!("splitting world into {} plates from layout", layout.plates.len());
+        let verts = make_plate_points(poly);
+
+        let positions = verts.iter().map(|v| v.pos).collect();
+        let plate_points = try!(layout.assign_points(&positions));
+
+        let mut plates = Vec::with_capacity(layout.plates.len());
+        let mut crusts = Vec::with_capacity(layout.plates.len());
+        for (desc, points) in layout.plates.iter().zip(plate_points.into_iter()) {
+            let plate = Plate::new(points, &try!(desc.euler_pole_vec()),
+                                   desc.angular_velocity, desc.height);
+            crusts.push(match try!(desc.crust_type()) {
+                Some(crust) => crust,
+                None => plate.initial_crust()
+            });
+            plates.push(plate);
         }
 
-        let plates = random_partition(rng, &verts, num_plates);
-        for plate in plates.iter() {
+        Ok(PlateSimulation::from_plates(poly, params, verts, plates, crusts, rng))
+    }
+
+    /// Creates a simulation in which all continental crust is gathered in
+    /// a single supercontinent: a spherical cap covering
+    /// `continental_fraction` of the surface, carried by one plate and
+    /// surrounded by up to `num_plates - 1` oceanic plates.
+    pub fn supercontinent<R: Rng>(poly: &Polyhedron,
+                                  params: &PlateSimParams,
+                                  continental_fraction: f32,
+                                  rng: &mut R) -> PlateSimulation {
+        // continents stand this much above the highest oceanic plate
+        const CONTINENT_HEIGHT: f32 = 1.02;
+
+        let num_plates = params.num_plates;
+        if num_plates < 2 || poly.faces.len() < num_plates {
+            panic_bt!("cannot split {} faces into a supercontinent and oceanic plates, {} plates total",
+                      poly.faces.len(), num_plates);
+        }
+
+        println!("gathering continents into a supercontinent, {} plates total", num_plates);
+        let verts = make_plate_points(poly);
+
+        let mesh = sphere_mesh(poly, &verts);
+
+        // area of a cap with angular radius r is 2 * PI * (1 - cos r)
+        let center = random_pole(rng);
+        let min_dot = 1.0 - 2.0 * continental_fraction;
+        let is_continental = |idx: &uint| center.dot(&mesh.positions[*idx]) >= min_dot;
+
+        let continent: Vec<uint> = range(0u, verts.len()).filter(|idx| is_continental(idx)).collect();
+        if continent.is_empty() {
+            panic_bt!("continental fraction {} too small for {} points",
+                      continental_fraction, verts.len());
+        }
+
+        let mut plates = vec![Plate::from_points(rng, continent)];
+        plates[0].height = CONTINENT_HEIGHT;
+
+        for mut points in partition::partition(params.partition, rng, &mesh,
+                                               num_plates - 1).into_iter() {
+            points.retain(|idx| !is_continental(idx));
+            if points.is_empty() {
+                continue;
+            }
+
+            let mut plate = Plate::from_points(rng, points);
+            // keep oceanic plates below the continental ones
+            plate.height = plate.height.min(2.0 - plate.height);
+            plates.push(plate);
+        }
+
+        let mut crusts = Vec::from_elem(plates.len(), CrustType::Oceanic);
+        crusts[0] = CrustType::Continental;
+
+        PlateSimulation::from_plates(poly, params, verts, plates, crusts, rng)
+    }
+
+    fn from_plates<R: Rng>(poly: &Polyhedron,
+                           params: &PlateSimParams,
+                           mut verts: Vec<PlatePoint>,
+                           plates: Vec<Plate>,
+                           crusts: Vec<CrustType>,
+                           rng: &mut R) -> PlateSimulation {
+        for plate_idx in range(0u, plates.len()) {
+            let plate = &plates[plate_idx];
             for &vert_idx in plate.vertex_indices.iter() {
-                verts[vert_idx].speed = plate.move_speed;
+                verts[vert_idx].angular_velocity = plate.angular_velocity;
+                verts[vert_idx].plate_idx = plate_idx;
+                verts[vert_idx].crust = crusts[plate_idx].clone();
             }
         }
 
+        let convection = if params.convection_interval > 0 {
+            Some(MantleConvection::new(rng, params.convection_interval))
+        } else {
+            None
+        };
+
+        let hotspots = range(0u, params.num_hotspots)
+                           .map(|_| Hotspot::new(rng, params.hotspot_strength))
+                           .collect();
+        let history = RotationHistory::new(verts.iter().map(|v| v.plate_idx).collect());
+
         PlateSimulation {
             initial_distance: get_edge_length(poly),
+            planet_radius_km: params.planet_radius_km,
+            step_myr: params.step_myr,
             verts: verts,
-            plates: plates
+            plates: plates,
+            collision_steps: TreeMap::new(),
+            edges: poly.edges.clone(),
+            faces: poly.faces.iter().map(|f| f.vertex_indices).collect(),
+            step: 0,
+            convection: convection,
+            hotspots: hotspots,
+            history: history,
+            fault_stress: FaultStress::new(),
+            events: EventLog::new(),
+            num_threads: params.num_threads
         }
     }
 
-    fn simulate_plates_step(&mut self) {
-        const DOT_THRESHOLD: f32 = 0.5;
-        let initial_distance: f32 = self.initial_distance;
+    pub fn num_plates(&self) -> uint {
+        self.plates.len()
+    }
+
+    pub fn plates(&self) -> &Vec<Plate> {
+        &self.plates
+    }
+
+    /// Number of steps simulated so far.
+    pub fn step(&self) -> uint {
+        self.step
+    }
+
+    /// Thread count is not part of the simulation state: results are
+    /// identical for any number of threads.
+    pub fn set_num_threads(&mut self, num_threads: uint) {
+        self.num_threads = num_threads;
+    }
 
+    pub fn write_to<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        try!(w.write_le_f32(self.initial_distance));
+        try!(w.write_le_f32(self.planet_radius_km));
+        try!(w.write_le_f32(self.step_myr));
+
+        try!(write_uint(w, self.verts.len()));
+        for v in self.verts.iter() {
+            try!(v.write_to(w));
+        }
+
+        try!(write_uint(w, self.plates.len()));
         for plate in self.plates.iter() {
-            plate.simulate(&mut self.verts);
+            try!(plate.write_to(w));
         }
 
-        let mut avg_distances = Vec::with_capacity(self.verts.len());
-        let mut min_dist = avg_distances.len() as f32;
-        let mut max_dist = 0.0f32;
+        try!(write_uint(w, self.collision_steps.len()));
+        for (&(a, b), &steps) in self.collision_steps.iter() {
+            try!(write_uint(w, a));
+            try!(write_uint(w, b));
+            try!(write_uint(w, steps));
+        }
 
-        for i in range(0u, self.verts.len()) {
-            let v = &self.verts[i];
-            let mut sum = 0.0f32;
+        try!(write_uint(w, self.edges.len()));
+        for edge in self.edges.iter() {
+            try!(write_uint(w, edge.vertex_indices[0]));
+            try!(write_uint(w, edge.vertex_indices[1]));
+            try!(write_uint_vec(w, &edge.face_indices));
+        }
+
+        try!(write_uint(w, self.faces.len()));
+        for face in self.faces.iter() {
+            for &idx in face.iter() {
+                try!(write_uint(w, idx));
+            }
+        }
+
+        try!(write_uint(w, self.step));
+
+        match self.convection {
+            Some(ref convection) => {
+                try!(write_bool(w, true));
+                try!(convection.write_to(w));
+            },
+            None => try!(write_bool(w, false))
+        }
+
+        try!(write_uint(w, self.hotspots.len()));
+        for hotspot in self.hotspots.iter() {
+            try!(hotspot.write_to(w));
+        }
+
+        try!(self.history.write_to(w));
+        try!(self.fault_stress.write_to(w));
+        self.events.write_to(w)
+    }
+
+    pub fn read_from<R: Reader>(r: &mut R) -> IoResult<PlateSimulation> {
+        let initial_distance = try!(r.read_le_f32());
+        let planet_radius_km = try!(r.read_le_f32());
+        let step_myr = try!(r.read_le_f32());
+
+        let num_verts = try!(read_uint(r));
+        let mut verts = Vec::new();
+        for _ in range(0u, num_verts) {
+            verts.push(try!(PlatePoint::read_from(r)));
+        }
+
+        let num_plates = try!(read_uint(r));
+        let mut plates = Vec::new();
+        for _ in range(0u, num_plates) {
+            plates.push(try!(Plate::read_from(r)));
+        }
+
+        let mut collision_steps = TreeMap::new();
+        for _ in range(0u, try!(read_uint(r))) {
+            let a = try!(read_uint(r));
+            let b = try!(read_uint(r));
+            collision_steps.insert((a, b), try!(read_uint(r)));
+        }
+
+        let num_edges = try!(read_uint(r));
+        let mut edges = Vec::new();
+        for _ in range(0u, num_edges) {
+            let a = try!(read_uint(r));
+            let b = try!(read_uint(r));
+            edges.push(Edge {
+                vertex_indices: [a, b],
+                face_indices: try!(read_uint_vec(r))
+            });
+        }
+
+        let num_faces = try!(read_uint(r));
+        let mut faces = Vec::new();
+        for _ in range(0u, num_faces) {
+            let mut face = [0u, ..3];
+            for i in range(0u, 3) {
+                face[i] = try!(read_uint(r));
+            }
+            faces.push(face);
+        }
+
+        let step = try!(read_uint(r));
+
+        let convection = if try!(read_bool(r)) {
+            Some(try!(MantleConvection::read_from(r)))
+        } else {
+            None
+        };
+
+        let num_hotspots = try!(read_uint(r));
+        let mut hotspots = Vec::new();
+        for _ in range(0u, num_hotspots) {
+            hotspots.push(try!(Hotspot::read_from(r)));
+        }
+
+        let history = try!(RotationHistory::read_from(r));
+        let fault_stress = try!(FaultStress::read_from(r));
+        let events = try!(EventLog::read_from(r));
+
+        let bad_vert = |idx: &uint| *idx >= num_verts;
+        let bad_plate = |idx: &uint| *idx >= num_plates;
+
+        if verts.iter().any(|v| bad_plate(&v.plate_idx)
+                                || v.nbr_indices.iter().any(|i| bad_vert(i))
+                                || v.volcanism.as_ref().map_or(false, |record| {
+                                       record.hotspot_idx >= num_hotspots
+                                   }))
+                || plates.iter().any(|p| p.vertex_indices.iter().any(|i| bad_vert(i)))
+                || collision_steps.keys().any(|&(a, b)| bad_plate(&a) || bad_plate(&b))
+                || edges.iter().any(|e| e.vertex_indices.iter().any(|i| bad_vert(i))
+                                        || e.face_indices.iter().any(|&i| i >= num_faces))
+                || faces.iter().any(|f| f.iter().any(|i| bad_vert(i))) {
+            return Err(invalid_data("inconsistent plate simulation checkpoint"));
+        }
+        try!(history.validate(num_verts, num_plates, step));
+
+        Ok(PlateSimulation {
+            initial_distance: initial_distance,
+            planet_radius_km: planet_radius_km,
+            step_myr: step_myr,
+            verts: verts,
+            plates: plates,
+            collision_steps: collision_steps,
+            edges: edges,
+            faces: faces,
+            step: step,
+            convection: convection,
+            hotspots: hotspots,
+            history: history,
+            fault_stress: fault_stress,
+            events: events,
+            num_threads: 1
+        })
+    }
+
+    pub fn planet_radius_km(&self) -> f32 {
+        self.planet_radius_km
+    }
+
+    pub fn step_myr(&self) -> f32 {
+        self.step_myr
+    }
+
+    /// Velocity of a point, in unit-sphere radians per Myr.
+    fn point_velocity(&self, vert_idx: uint) -> Vector3<f32> {
+        let v = &self.verts[vert_idx];
+        let plate = &self.plates[v.plate_idx];
+        plate.euler_pole.cross(&v.pos).mul_s(deg(v.angular_velocity).to_rad().s)
+    }
+
+    /// Surface velocity of a point, in cm/yr.
+    pub fn surface_velocity(&self, vert_idx: uint) -> f32 {
+        let v = &self.verts[vert_idx];
+        let pole = &self.plates[v.plate_idx].euler_pole;
+        let dist_from_pole = angle_between(pole, &v.pos);
+
+        surface_velocity_cm_per_yr(v.angular_velocity, dist_from_pole, self.planet_radius_km)
+    }
+
+    /// Returns minimum, mean and maximum surface velocity over all points, in cm/yr.
+    pub fn surface_velocity_range(&self) -> (f32, f32, f32) {
+        let mut min_vel = Float::infinity();
+        let mut max_vel = 0.0f32;
+        let mut sum = 0.0f32;
+
+        for idx in range(0u, self.verts.len()) {
+            let vel = self.surface_velocity(idx);
+            min_vel = min_vel.min(vel);
+            max_vel = max_vel.max(vel);
+            sum += vel;
+        }
+
+        (min_vel, sum / self.verts.len() as f32, max_vel)
+    }
+
+    pub fn print_velocity_report(&self) {
+        let (min_vel, mean_vel, max_vel) = self.surface_velocity_range();
+        let (earth_min, earth_max) = EARTH_PLATE_VELOCITY_CM_PER_YR;
+
+        println!("plate surface velocities: min {} cm/yr, mean {} cm/yr, max {} cm/yr",
+                 min_vel, mean_vel, max_vel);
+        println!("(Earth: {} - {} cm/yr)", earth_min, earth_max);
+    }
 
-            for v2 in self.verts.iter() {
-                sum += v.pos.dot(&v2.pos).max(DOT_THRESHOLD);
+    fn face_center(&self, face_idx: uint) -> Vector3<f32> {
+        let face = &self.faces[face_idx];
+        self.verts[face[0]].pos.add(&self.verts[face[1]].pos)
+                               .add(&self.verts[face[2]].pos)
+                               .normalize()
+    }
+
+    /// Returns a segment for every mesh edge connecting points of two
+    /// different plates. Segments are oriented so that the lower plate
+    /// index comes first.
+    pub fn boundary_segments(&self) -> Vec<BoundarySegment> {
+        boundary_edges(self).into_iter().map(|boundary| {
+            let edge = &self.edges[boundary.edge_idx];
+            let points = boundary.vertex_indices;
+            let midpoint = self.verts[points[0]].pos.add(&self.verts[points[1]].pos).normalize();
+            let endpoints = if edge.face_indices.len() == 2 {
+                [self.face_center(edge.face_indices[0]), self.face_center(edge.face_indices[1])]
+            } else {
+                [midpoint, midpoint]
+            };
+
+            BoundarySegment {
+                plate_indices: boundary.plate_indices,
+                vertex_indices: boundary.vertex_indices,
+                endpoints: endpoints,
+                boundary_type: boundary.boundary_type,
+                closing_speed: boundary.closing_speed,
+                relative_speed: boundary.relative_speed
+            }
+        }).collect()
+    }
+
+    /// Builds the graph of plates sharing a boundary.
+    pub fn adjacency_graph(&self) -> PlateGraph {
+        let plate_sizes = self.plates.iter().map(|p| p.vertex_indices.len()).collect();
+        PlateGraph::from_segments(&self.boundary_segments(), plate_sizes, self.planet_radius_km)
+    }
+
+    /// Writes boundary segments as Wavefront OBJ line geometry, grouped by
+    /// boundary type.
+    pub fn write_boundaries_obj<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        let segments = self.boundary_segments();
+
+        try!(writeln!(w, "# plate boundaries, {} segments", segments.len()));
+        for segment in segments.iter() {
+            for p in segment.endpoints.iter() {
+                try!(writeln!(w, "v {} {} {}", p.x, p.y, p.z));
+            }
+        }
+
+        for boundary_type in [BoundaryType::Convergent,
+                              BoundaryType::Divergent,
+                              BoundaryType::Transform].iter() {
+            try!(writeln!(w, "g {}", boundary_type.name()));
+
+            for i in range(0u, segments.len()) {
+                if segments[i].boundary_type == *boundary_type {
+                    // OBJ indices are 1-based
+                    try!(writeln!(w, "l {} {}", i * 2 + 1, i * 2 + 2));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Welds plate `absorbed_idx` into plate `plate_idx`. The merged plate
+    /// rotates with the point-count-weighted mean angular velocity of both.
+    fn merge_plates(&mut self, plate_idx: uint, absorbed_idx: uint) {
+        let absorbed = self.plates.remove(absorbed_idx).unwrap();
+        let plate_idx = if absorbed_idx < plate_idx { plate_idx - 1 } else { plate_idx };
+
+        let sizes = [self.plates[plate_idx].vertex_indices.len() as f32,
+                     absorbed.vertex_indices.len() as f32];
+        let total = sizes[0] + sizes[1];
+
+        let omega = self.plates[plate_idx].rotation_vector().mul_s(sizes[0])
+                        .add(&absorbed.rotation_vector().mul_s(sizes[1]))
+                        .div_s(total);
+
+        // express the absorbed points' damping relative to the surviving plate
+        let speed_ratio = if absorbed.angular_velocity > 0.0 {
+            self.plates[plate_idx].angular_velocity / absorbed.angular_velocity
+        } else {
+            1.0
+        };
+
+        for v in self.verts.iter_mut() {
+            if v.plate_idx == absorbed_idx {
+                v.plate_idx = plate_idx;
+                v.angular_velocity *= speed_ratio;
+            } else if v.plate_idx > absorbed_idx {
+                v.plate_idx -= 1;
+            }
+        }
+
+        {
+            let plate = &mut self.plates[plate_idx];
+            plate.height = (plate.height * sizes[0] + absorbed.height * sizes[1]) / total;
+            plate.vertex_indices.push_all(absorbed.vertex_indices.as_slice());
+        }
+
+        self.set_plate_rotation(plate_idx, &omega);
+        self.collision_steps = TreeMap::new();
+
+        // plates are merged after moving, new membership applies from the next step
+        let assignment = self.verts.iter().map(|v| v.plate_idx).collect();
+        self.history.record_assignment(self.step + 1, assignment);
+    }
+
+    /// Changes plate rotation while keeping the damping already applied
+    /// to each of its points.
+    fn set_plate_rotation(&mut self, plate_idx: uint, rotation: &Vector3<f32>) {
+        let old_speed = self.plates[plate_idx].angular_velocity;
+        let new_speed = rotation.length();
+
+        {
+            let plate = &mut self.plates[plate_idx];
+            if new_speed > 0.0 {
+                plate.euler_pole = rotation.normalize();
+            }
+            plate.angular_velocity = new_speed;
+        }
+
+        for &idx in self.plates[plate_idx].vertex_indices.iter() {
+            let v = &mut self.verts[idx];
+            v.angular_velocity = if old_speed > 0.0 {
+                new_speed * v.angular_velocity / old_speed
+            } else {
+                new_speed
+            };
+        }
+    }
+
+    /// Surface velocity each point would have if dragged by the mantle
+    /// flow, plus slab pull on oceanic points subducting under a more
+    /// buoyant plate. In radians per Myr.
+    fn driving_velocities(&self, convection: &MantleConvection) -> Vec<Vector3<f32>> {
+        let mut velocities: Vec<Vector3<f32>> =
+            self.verts.iter().map(|v| convection.flow_at(&v.pos)).collect();
+
+        for idx in range(0u, self.verts.len()) {
+            let v = &self.verts[idx];
+            if v.crust != CrustType::Oceanic {
+                continue;
+            }
+
+            for &nbr_idx in v.nbr_indices.iter() {
+                let nbr = &self.verts[nbr_idx];
+                if nbr.plate_idx == v.plate_idx
+                        || self.plates[nbr.plate_idx].height <= self.plates[v.plate_idx].height {
+                    continue;
+                }
+
+                let pull = slab_pull_direction(&v.pos, &nbr.pos).mul_s(convection.slab_pull);
+                velocities[idx] = velocities[idx].add(&pull);
+            }
+        }
+
+        velocities
+    }
+
+    fn drive_plates_by_convection(&mut self) {
+        let targets = match self.convection {
+            Some(ref convection) => {
+                let velocities = self.driving_velocities(convection);
+
+                self.plates.iter().map(|plate| {
+                    let points: Vec<Vector3<f32>> =
+                        plate.vertex_indices.iter().map(|&i| self.verts[i].pos).collect();
+                    let plate_velocities: Vec<Vector3<f32>> =
+                        plate.vertex_indices.iter().map(|&i| velocities[i]).collect();
+                    // rad/Myr -> deg/Myr
+                    fit_rotation(points.as_slice(), plate_velocities.as_slice())
+                        .map(|w| (w.mul_s(180.0 / PI), convection.response))
+                }).collect::<Vec<Option<(Vector3<f32>, f32)>>>()
+            },
+            None => return
+        };
+
+        for plate_idx in range(0u, targets.len()) {
+            match targets[plate_idx] {
+                Some((target, response)) => {
+                    let current = self.plates[plate_idx].rotation_vector();
+                    let rotation = current.add(&target.sub(&current).mul_s(response));
+                    self.set_plate_rotation(plate_idx, &rotation);
+                },
+                None => {}
+            }
+        }
+    }
+
+    /// Splits a plate along a random great circle through its centroid.
+    /// Both parts keep the original plate motion, plus `rift_speed` deg/Myr
+    /// of rotation pulling them apart. Returns the index of the new plate,
+    /// or None if the cut leaves one of the parts empty.
+    pub fn split_plate<R: Rng>(&mut self,
+                               plate_idx: uint,
+                               rift_speed: f32,
+                               rng: &mut R) -> Option<uint> {
+        let centroid = self.plates[plate_idx].vertex_indices.iter()
+                           .fold(Vector3::new(0.0f32, 0.0, 0.0),
+                                 |sum, &idx| sum.add(&self.verts[idx].pos));
+        if centroid.length2() == 0.0 {
+            return None;
+        }
+        let centroid = centroid.normalize();
+
+        let normal = centroid.cross(&random_pole(rng));
+        if normal.length2() == 0.0 {
+            return None;
+        }
+        let normal = normal.normalize();
+
+        let mut kept = Vec::new();
+        let mut moved = Vec::new();
+        for &idx in self.plates[plate_idx].vertex_indices.iter() {
+            if self.verts[idx].pos.dot(&normal) > 0.0 {
+                moved.push(idx);
+            } else {
+                kept.push(idx);
+            }
+        }
+        if kept.is_empty() || moved.is_empty() {
+            return None;
+        }
+
+        let new_idx = self.plates.len();
+        let omega = self.plates[plate_idx].rotation_vector();
+        // rotation around centroid x normal moves the centroid along the normal
+        let rift = centroid.cross(&normal).mul_s(rift_speed);
+
+        for &idx in moved.iter() {
+            self.verts[idx].plate_idx = new_idx;
+        }
+
+        let plate = {
+            let old = &mut self.plates[plate_idx];
+            old.vertex_indices = kept;
+            Plate::new(moved, &old.euler_pole, old.angular_velocity, old.height)
+        };
+        self.plates.push(plate);
+
+        self.set_plate_rotation(new_idx, &omega.add(&rift));
+        self.set_plate_rotation(plate_idx, &omega.sub(&rift));
+        self.collision_steps = TreeMap::new();
+
+        // plates are split between steps, new membership applies right away
+        let assignment = self.verts.iter().map(|v| v.plate_idx).collect();
+        self.history.record_assignment(self.step, assignment);
+        self.events.push(Event::new(self.step, EventKind::Rift, &centroid, [plate_idx, new_idx]));
+
+        Some(new_idx)
+    }
+
+    /// Groups continental points into landmasses. Mesh edges stop
+    /// connecting nearby points once plates drift, so two continental
+    /// points belong to the same landmass if they are closer than
+    /// `LANDMASS_LINK_RATIO` initial edge lengths.
+    pub fn continental_landmasses(&self) -> Vec<Vec<uint>> {
+        const LANDMASS_LINK_RATIO: f32 = 1.5;
+
+        let max_dist2 = (self.initial_distance * LANDMASS_LINK_RATIO).powi(2);
+        let continental: Vec<uint> =
+            range(0u, self.verts.len())
+                .filter(|&idx| self.verts[idx].crust == CrustType::Continental)
+                .collect();
+
+        let mut visited = Vec::from_elem(continental.len(), false);
+        let mut landmasses = Vec::new();
+
+        for start in range(0u, continental.len()) {
+            if visited[start] {
+                continue;
+            }
+
+            visited[start] = true;
+            let mut queue = vec![start];
+            let mut next = 0u;
+
+            while next < queue.len() {
+                let pos = self.verts[continental[queue[next]]].pos;
+                next += 1;
+
+                for other in range(0u, continental.len()) {
+                    if !visited[other]
+                            && self.verts[continental[other]].pos.sub(&pos).length2() <= max_dist2 {
+                        visited[other] = true;
+                        queue.push(other);
+                    }
+                }
+            }
+
+            landmasses.push(queue.iter().map(|&i| continental[i]).collect());
+        }
+
+        landmasses
+    }
+
+    /// Mean position of the boundary between two plates.
+    fn boundary_center(&self, a: uint, b: uint) -> Vector3<f32> {
+        let sum = self.boundary_segments().iter()
+                      .filter(|s| s.plate_indices[0] == a && s.plate_indices[1] == b)
+                      .fold(Vector3::new(0.0f32, 0.0, 0.0),
+                            |sum, s| sum.add(&s.endpoints[0]).add(&s.endpoints[1]));
+        if sum.length2() > 0.0 {
+            sum.normalize()
+        } else {
+            self.verts[self.plates[a].vertex_indices[0]].pos
+        }
+    }
+
+    /// If the boundary between given points is a subduction zone, returns
+    /// the point on the overriding side.
+    fn overriding_point(&self, a: uint, b: uint) -> Option<uint> {
+        let height = |idx: uint| self.plates[self.verts[idx].plate_idx].height;
+
+        if self.verts[a].crust == CrustType::Oceanic && height(a) < height(b) {
+            Some(b)
+        } else if self.verts[b].crust == CrustType::Oceanic && height(b) < height(a) {
+            Some(a)
+        } else {
+            None
+        }
+    }
+
+    /// Loads plate boundaries with a step of relative motion and records
+    /// released stress as earthquakes. Earthquakes in subduction zones are
+    /// accompanied by arc volcanism on the overriding plate, those on
+    /// spreading ridges by a rift between the two plates.
+    fn release_boundary_stress(&mut self) {
+        let segments = self.boundary_segments();
+        let ruptures = self.fault_stress.accumulate(&segments, self.step_myr,
+                                                    self.planet_radius_km);
+        let mut rifted_pairs = Vec::new();
+
+        for &(segment_idx, magnitude) in ruptures.iter() {
+            let segment = &segments[segment_idx];
+            let pos = segment.endpoints[0].add(&segment.endpoints[1]).normalize();
+
+            let mut quake = Event::new(self.step, EventKind::Earthquake, &pos,
+                                       segment.plate_indices);
+            quake.magnitude = magnitude;
+            quake.boundary_type = Some(segment.boundary_type.clone());
+            self.events.push(quake);
+
+            if segment.boundary_type == BoundaryType::Divergent {
+                // a single rift per pair of plates and step, however many
+                // segments of the ridge between them rupture
+                let pair = (segment.plate_indices[0], segment.plate_indices[1]);
+                if !rifted_pairs.contains(&pair) {
+                    rifted_pairs.push(pair);
+
+                    let mut rift = Event::new(self.step, EventKind::Rift, &pos,
+                                              segment.plate_indices);
+                    rift.boundary_type = Some(BoundaryType::Divergent);
+                    self.events.push(rift);
+                }
             }
 
-            sum -= self.verts.len() as f32 * DOT_THRESHOLD;
-            let avg_dist = sum / self.verts.len() as f32;
-            avg_distances.push(avg_dist);
+            if segment.boundary_type != BoundaryType::Convergent {
+                continue;
+            }
+
+            match self.overriding_point(segment.vertex_indices[0], segment.vertex_indices[1]) {
+                Some(idx) => {
+                    let (over, under) = if idx == segment.vertex_indices[0] {
+                        (segment.plate_indices[0], segment.plate_indices[1])
+                    } else {
+                        (segment.plate_indices[1], segment.plate_indices[0])
+                    };
+
+                    let mut eruption = Event::new(self.step, EventKind::Eruption,
+                                                  &self.verts[idx].pos, [over, under]);
+                    eruption.boundary_type = Some(BoundaryType::Convergent);
+                    self.events.push(eruption);
+                },
+                None => {}
+            }
+        }
+    }
+
+    pub fn events(&self) -> &EventLog {
+        &self.events
+    }
+
+    fn suture_colliding_plates(&mut self) {
+        let head_on = head_on_collisions(boundary_edges(&*self).as_slice());
+        let (collision_steps, to_merge) = {
+            let plates = &self.plates;
+            let verts = &self.verts;
+            track_collisions(&self.collision_steps, head_on.as_slice(),
+                             |a, b| plates[a].is_continental(verts)
+                                    && plates[b].is_continental(verts))
+        };
+        self.collision_steps = collision_steps;
+
+        match to_merge {
+            Some((a, b)) => {
+                println!("suturing plates {} and {}", a, b);
+                let pos = self.boundary_center(a, b);
+                self.events.push(Event::new(self.step, EventKind::Suture, &pos, [a, b]));
+                self.merge_plates(a, b);
+            },
+            None => {}
+        }
+    }
+
+    pub fn hotspots(&self) -> &Vec<Hotspot> {
+        &self.hotspots
+    }
+
+    /// Raises the crust currently above any of the hotspots.
+    fn apply_hotspots(&mut self) {
+        let radius = plume_radius(self.initial_distance);
+        let mut eruptions = Vec::new();
+
+        for hotspot_idx in range(0u, self.hotspots.len()) {
+            let hotspot = &self.hotspots[hotspot_idx];
+            // plate carrying the crust closest to the plume center
+            let mut max_uplift = 0.0f32;
+            let mut plate_idx = 0u;
+
+            for v in self.verts.iter_mut() {
+                let uplift = hotspot.uplift_at(&v.pos, radius);
+                if uplift > 0.0 {
+                    v.volcanic_height += uplift * self.step_myr;
+                    v.volcanism = Some(VolcanicRecord {
+                        hotspot_idx: hotspot_idx,
+                        last_step: self.step
+                    });
+
+                    if uplift > max_uplift {
+                        max_uplift = uplift;
+                        plate_idx = v.plate_idx;
+                    }
+                }
+            }
+
+            if max_uplift > 0.0 {
+                eruptions.push(Event::new(self.step, EventKind::Eruption, &hotspot.pos,
+                                          [plate_idx, plate_idx]));
+            }
+        }
+
+        for eruption in eruptions.into_iter() {
+            self.events.push(eruption);
+        }
+    }
+
+    /// Returns indices of points that erupted over the given hotspot,
+    /// youngest first. As the crust drifts away from the plume, the result
+    /// traces an age-progressive island chain.
+    pub fn island_chain(&self, hotspot_idx: uint) -> Vec<uint> {
+        let mut chain: Vec<(uint, uint)> = Vec::new();
+
+        for idx in range(0u, self.verts.len()) {
+            match self.verts[idx].volcanism {
+                Some(ref record) if record.hotspot_idx == hotspot_idx =>
+                    chain.push((record.last_step, idx)),
+                _ => {}
+            }
+        }
 
-            min_dist = min_dist.min(avg_dist);
-            max_dist = max_dist.max(avg_dist);
+        chain.sort_by(|a, b| b.cmp(a));
+        chain.iter().map(|&(_, idx)| idx).collect()
+    }
+
+    fn simulate_plates_step(&mut self) {
+        let initial_distance: f32 = self.initial_distance;
+
+        let update_velocities = match self.convection {
+            Some(ref convection) => convection.is_update_step(self.step),
+            None => false
+        };
+        if update_velocities {
+            self.drive_plates_by_convection();
         }
 
+        let stage = StageRotation {
+            euler_poles: self.plates.iter().map(|plate| plate.euler_pole).collect(),
+            angles: self.plate_step_angles()
+        };
+        self.history.record_step(stage);
+
+        for plate in self.plates.iter() {
+            plate.simulate(&mut self.verts, self.step_myr);
+        }
+
+        let positions: Vec<Vector3<f32>> = self.verts.iter().map(|v| v.pos).collect();
+        let avg_distances = parallel::map_indices(Arc::new(positions), self.verts.len(),
+                                                  self.num_threads, avg_distance);
+
         let speed_scale = |i| 1.0 - (avg_distances[i] / initial_distance);
 
         for i in range(0u, self.verts.len()) {
-            self.verts[i].speed.s *= speed_scale(i);
+            self.verts[i].angular_velocity *= speed_scale(i);
         }
+
+        self.release_boundary_stress();
+        self.apply_hotspots();
+        self.suture_colliding_plates();
+        self.step += 1;
     }
 
     pub fn simulate_plates(&mut self, steps: uint) {
@@ -262,30 +1330,104 @@ impl PlateSimulation {
         }
     }
 
-    fn get_vertices(&self) -> Vec<Vertex> {
-        let mut vertices = Vec::with_capacity(self.verts.len() * 2 + 1);
+    /// Angle every plate turns around its pole during the next step, in
+    /// radians: the mean over its points, some of which are slowed down
+    /// near plate boundaries.
+    fn plate_step_angles(&self) -> Vec<f32> {
+        self.plates.iter().map(|plate| {
+            let sum = plate.vertex_indices.iter().fold(0.0f32, |sum, &idx| {
+                sum + self.verts[idx].step_angle(self.step_myr).s
+            });
+            sum / plate.vertex_indices.len() as f32
+        }).collect()
+    }
+
+    /// Positions of all points at an earlier `step`, reconstructed from
+    /// the recorded rotations. None if `step` is in the future.
+    pub fn reconstruct(&self, step: uint) -> Option<Vec<Vector3<f32>>> {
+        let positions = self.verts.iter().map(|v| v.pos).collect();
+        self.history.reconstruct(&positions, step)
+    }
+
+    /// Rotates `positions` of all points from the end of an earlier `step`
+    /// back to its start.
+    pub fn step_back(&self, positions: &mut Vec<Vector3<f32>>, step: uint) {
+        self.history.step_back(positions, step);
+    }
+
+    /// Point vertices colored by plate, each with a line segment showing
+    /// its movement during the step.
+    fn get_vertices(&self,
+                    positions: &Vec<Vector3<f32>>,
+                    assignment: &Vec<uint>,
+                    euler_poles: &Vec<Vector3<f32>>,
+                    angles: &Vec<f32>) -> Vec<Vertex> {
+        let mut vertices = Vec::with_capacity(positions.len() * 2 + 1);
         vertices.push(make_vertex(&Vector3::new(0.0, 0.0, 0.0), &[0.0, 0.0, 0.0, 1.0]));
 
-        for plate_idx in range(0u, self.plates.len()) {
-            let plate = &self.plates[plate_idx];
-            let plate_color = color_by_index(plate_idx, self.plates.len());
+        for vert_idx in range(0u, positions.len()) {
+            let plate_idx = assignment[vert_idx];
+            let plate_color = color_by_index(plate_idx, euler_poles.len());
+            let pos = &positions[vert_idx];
+            let rot: Basis3<f32> = Rotation3::from_axis_angle(&euler_poles[plate_idx],
+                                                              rad(angles[plate_idx]));
 
-            for &vert_idx in plate.vertex_indices.iter() {
-                let v = &self.verts[vert_idx];
-                let rot: Basis3<f32> = Rotation3::from_axis_angle(&plate.move_axis, v.speed);
+            vertices.push(make_vertex(pos, &plate_color));
+            vertices.push(make_vertex(&rot.rotate_vector(pos), &plate_color));
+        }
+
+        vertices
+    }
 
-                vertices.push(make_vertex(&v.pos, &plate_color));
-                vertices.push(make_vertex(&rot.rotate_vector(&v.pos), &plate_color));
+    /// Creates a batch of boundary lines, colored by boundary type,
+    /// floating slightly above the sphere.
+    pub fn boundaries_to_batch(&self,
+                               ctx: &mut Context,
+                               dev: &mut GlDevice) -> PolyhedronBatch {
+        const ELEVATION: f32 = 1.01;
+
+        let segments = self.boundary_segments();
+        let mut vertices = Vec::with_capacity(segments.len() * 2);
+        for segment in segments.iter() {
+            let color = segment.boundary_type.color();
+            for p in segment.endpoints.iter() {
+                vertices.push(make_vertex(&p.mul_s(ELEVATION), &color));
             }
         }
+        let mesh = dev.create_mesh(vertices.as_slice());
 
-        vertices
+        let indices = range(0u32, vertices.len() as u32).collect::<Vec<u32>>();
+        let idx_slice = dev.create_buffer_static(indices.as_slice())
+                           .to_slice(gfx::PrimitiveType::Line);
+
+        let shader = dev.link_program(rendering::VS_SOURCE.clone(), rendering::FS_SOURCE.clone())
+                        .unwrap();
+        let state = gfx::DrawState::new().depth(gfx::state::Comparison::LessEqual, true);
+
+        ctx.make_batch(&shader, &mesh, idx_slice, &state).unwrap()
     }
 
+    /// Creates a point batch showing the simulation as it was at `step`,
+    /// with point `positions` at that step, e.g. from `reconstruct`.
+    /// None if `step` is in the future.
     pub fn to_batch(&self,
                     ctx: &mut Context,
-                    dev: &mut GlDevice) -> PolyhedronBatch {
-        let vertices = self.get_vertices();
+                    dev: &mut GlDevice,
+                    step: uint,
+                    positions: &Vec<Vector3<f32>>) -> Option<PolyhedronBatch> {
+        if step > self.step {
+            return None;
+        }
+        let vertices = match self.history.stage(step) {
+            Some(stage) => self.get_vertices(positions, self.history.assignment_at(step),
+                                             &stage.euler_poles, &stage.angles),
+            // current step, not simulated yet
+            None => {
+                let assignment = self.verts.iter().map(|v| v.plate_idx).collect();
+                let euler_poles = self.plates.iter().map(|p| p.euler_pole).collect();
+                self.get_vertices(positions, &assignment, &euler_poles, &self.plate_step_angles())
+            }
+        };
         let mesh = dev.create_mesh(vertices.as_slice());
 
         let mut indices: Vec<u32> = Vec::with_capacity(self.verts.len() * 4);
@@ -302,7 +1444,131 @@ impl PlateSimulation {
                         .unwrap();
         let state = gfx::DrawState::new().depth(gfx::state::Comparison::LessEqual, true);
 
-        ctx.make_batch(&shader, &mesh, idx_slice, &state).unwrap()
+        Some(ctx.make_batch(&shader, &mesh, idx_slice, &state).unwrap())
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use std::rand::SeedableRng;
+    use cgmath::Vector3;
+
+    use polyhedron::make_sphere;
+    use plate_layout::{PlateDesc, PlateLayout};
+    use events::EventKind;
+    use sim_rng::SimRng;
+    use world::World;
+    use geo::{from_lat_lon_deg, lat_lon_deg};
+    use hotspot::Hotspot;
+
+    use super::{PlateSimulation, PlateSimParams, BoundaryType};
+
+    fn hemisphere(seed_lat: f32, pole_lon: f32) -> PlateDesc {
+        PlateDesc {
+            seeds: Some(vec![vec![seed_lat, 0.0]]),
+            polygon: None,
+            euler_pole: vec![0.0, pole_lon],
+            angular_velocity: 1.5,
+            height: 1.0,
+            crust: None
+        }
+    }
+
+    #[test]
+    fn spreading_ridge_logs_a_rift() {
+        // hemispheres turning in opposite directions around the same axis
+        // pull apart along one half of the equator
+        let layout = PlateLayout {
+            plates: vec![hemisphere(90.0, 0.0), hemisphere(-90.0, 180.0)]
+        };
+        let mut rng: SimRng = SeedableRng::from_seed([1u32, 2, 3, 4]);
+        let mut sim = PlateSimulation::from_layout(&make_sphere(2), &PlateSimParams::new(2),
+                                                   &layout, &mut rng).unwrap();
+
+        sim.simulate_plates(4);
+
+        let rifts = sim.events().of_kind(EventKind::Rift);
+        assert!(!rifts.is_empty());
+        for rift in rifts.iter() {
+            assert!(rift.boundary_type == Some(BoundaryType::Divergent));
+            assert!(rift.plates[0] != rift.plates[1]);
+        }
+        // at most one rift per step between the two plates
+        assert!(rifts.len() <= sim.step());
+    }
+
+    #[test]
+    fn thread_count_does_not_change_results() {
+        let poly = make_sphere(2);
+        let run = |num_threads: uint| {
+            let params = PlateSimParams {
+                num_hotspots: 2,
+                num_threads: num_threads,
+                ..PlateSimParams::new(5)
+            };
+            let mut rng: SimRng = SeedableRng::from_seed([1u32, 2, 3, 4]);
+            let mut sim = PlateSimulation::new(&poly, &params, &mut rng);
+            sim.simulate_plates(4);
+
+            let mut world = World::new(make_sphere(3));
+            world.apply_heights(&sim, num_threads);
+
+            let positions: Vec<Vector3<f32>> = sim.verts.iter().map(|v| v.pos).collect();
+            (positions, world.heights())
+        };
+
+        let (serial_positions, serial_heights) = run(1);
+        let (parallel_positions, parallel_heights) = run(4);
+        assert!(serial_positions == parallel_positions);
+        assert!(serial_heights == parallel_heights);
+    }
+
+    #[test]
+    fn plate_over_hotspot_gets_age_ordered_chain() {
+        const NUM_STEPS: uint = 8;
+
+        // a single plate turning east over a plume on the equator, fast
+        // enough to keep moving while its points get slowed down
+        let layout = PlateLayout {
+            plates: vec![PlateDesc {
+                seeds: Some(vec![vec![0.0, 0.0]]),
+                polygon: None,
+                euler_pole: vec![90.0, 0.0],
+                angular_velocity: 3.0,
+                height: 1.0,
+                crust: None
+            }]
+        };
+        let mut rng: SimRng = SeedableRng::from_seed([1u32, 2, 3, 4]);
+        let mut sim = PlateSimulation::from_layout(&make_sphere(2), &PlateSimParams::new(1),
+                                                   &layout, &mut rng).unwrap();
+        sim.hotspots = vec![Hotspot { pos: from_lat_lon_deg(0.0, 0.0), strength: 0.001 }];
+
+        sim.simulate_plates(NUM_STEPS);
+
+        // mean longitude of the points that last erupted at every step
+        let chain = sim.island_chain(0);
+        let mut mean_lons = Vec::new();
+        for step in range(0u, NUM_STEPS) {
+            let lons: Vec<f32> = chain.iter()
+                                      .filter(|&&idx| sim.verts[idx].volcanism.as_ref()
+                                                         .unwrap().last_step == step)
+                                      .map(|&idx| {
+                                          let (_, lon) = lat_lon_deg(&sim.verts[idx].pos);
+                                          lon
+                                      })
+                                      .collect();
+            if !lons.is_empty() {
+                mean_lons.push(lons.iter().fold(0.0f32, |sum, &lon| sum + lon)
+                               / lons.len() as f32);
+            }
+        }
+
+        // older eruptions have drifted farther downstream
+        assert!(mean_lons.len() >= 3, "chain of {} steps", mean_lons.len());
+        for i in range(1u, mean_lons.len()) {
+            assert!(mean_lons[i - 1] > mean_lons[i], "{}", mean_lons);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+extern crate cgmath;
+extern crate serialize;
+
+use std::vec::Vec;
+use std::num::{Float, FloatMath};
+use std::f32::consts::PI;
+use std::io::IoResult;
+
+use cgmath::{EuclideanVector, Vector, Vector3};
+use serialize::json;
+
+use geo::{angle_between, lat_lon_deg};
+use plate_simulation::PlateSimulation;
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum StatsFormat {
+    Text,
+    Json
+}
+
+impl StatsFormat {
+    pub fn from_name(name: &str) -> Option<StatsFormat> {
+        match name {
+            "text" => Some(StatsFormat::Text),
+            "json" => Some(StatsFormat::Json),
+            _ => None
+        }
+    }
+}
+
+#[deriving(Clone, Show, Encodable)]
+pub struct PlateStats {
+    pub plate_idx: uint,
+    pub point_count: uint,
+    pub area_km2: f32,
+    /// Mean surface velocity of plate points, in cm/yr.
+    pub mean_speed_cm_per_yr: f32,
+    pub centroid_lat: f32,
+    pub centroid_lon: f32,
+    /// Angular radius of the smallest cap around the centroid containing
+    /// all plate points, in degrees.
+    pub bounding_cap_deg: f32,
+    pub boundary_length_km: f32,
+    /// Mean fraction of the plate angular velocity removed from its points
+    /// by speed damping. 0 means no damping, 1 means the points stopped.
+    pub speed_damping: f32
+}
+
+#[deriving(Clone, Show, Encodable)]
+pub struct StepStats {
+    pub step: uint,
+    pub time_myr: f32,
+    pub plates: Vec<PlateStats>
+}
+
+impl StepStats {
+    pub fn compute(sim: &PlateSimulation) -> StepStats {
+        let radius = sim.planet_radius_km();
+        let area_per_point = 4.0 * PI * radius * radius / sim.verts.len() as f32;
+
+        let mut boundary_lengths = Vec::from_elem(sim.num_plates(), 0.0f32);
+        for segment in sim.boundary_segments().iter() {
+            let length = angle_between(&segment.endpoints[0], &segment.endpoints[1]) * radius;
+            boundary_lengths[segment.plate_indices[0]] += length;
+            boundary_lengths[segment.plate_indices[1]] += length;
+        }
+
+        let plates = range(0u, sim.num_plates()).map(|plate_idx| {
+            let plate = &sim.plates()[plate_idx];
+            let indices = &plate.vertex_indices;
+            let count = indices.len() as f32;
+
+            let mut pos_sum = Vector3::new(0.0f32, 0.0, 0.0);
+            let mut speed_sum = 0.0f32;
+            let mut damping_sum = 0.0f32;
+            for &idx in indices.iter() {
+                pos_sum = pos_sum.add(&sim.verts[idx].pos);
+                speed_sum += sim.surface_velocity(idx);
+                if plate.angular_velocity > 0.0 {
+                    damping_sum += 1.0 - sim.verts[idx].angular_velocity / plate.angular_velocity;
+                }
+            }
+
+            let centroid = if pos_sum.length2() > 0.0 {
+                pos_sum.normalize()
+            } else {
+                Vector3::unit_z()
+            };
+            let (lat, lon) = lat_lon_deg(&centroid);
+
+            let cap = indices.iter()
+                             .map(|&idx| angle_between(&centroid, &sim.verts[idx].pos))
+                             .fold(0.0f32, |a, b| a.max(b));
+
+            PlateStats {
+                plate_idx: plate_idx,
+                point_count: indices.len(),
+                area_km2: count * area_per_point,
+                mean_speed_cm_per_yr: speed_sum / count,
+                centroid_lat: lat,
+                centroid_lon: lon,
+                bounding_cap_deg: cap.to_degrees(),
+                boundary_length_km: boundary_lengths[plate_idx],
+                speed_damping: damping_sum / count
+            }
+        }).collect();
+
+        StepStats {
+            step: sim.step(),
+            time_myr: sim.step() as f32 * sim.step_myr(),
+            plates: plates
+        }
+    }
+
+    pub fn write_to<W: Writer>(&self,
+                               w: &mut W,
+                               format: StatsFormat) -> IoResult<()> {
+        match format {
+            StatsFormat::Json => writeln!(w, "{}", json::encode(self)),
+            StatsFormat::Text => {
+                try!(writeln!(w, "step {} ({} Myr), {} plates",
+                              self.step, self.time_myr, self.plates.len()));
+                try!(writeln!(w, "{:>5} {:>6} {:>12} {:>8} {:>8} {:>8} {:>7} {:>10} {:>7}",
+                              "plate", "points", "area km2", "cm/yr", "lat", "lon",
+                              "cap", "border km", "damping"));
+
+                for p in self.plates.iter() {
+                    try!(writeln!(w, "{:>5} {:>6} {:>12.0} {:>8.2} {:>8.1} {:>8.1} {:>7.1} {:>10.0} {:>7.3}",
+                                  p.plate_idx, p.point_count, p.area_km2, p.mean_speed_cm_per_yr,
+                                  p.centroid_lat, p.centroid_lon, p.bounding_cap_deg,
+                                  p.boundary_length_km, p.speed_damping));
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
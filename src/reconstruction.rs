@@ -0,0 +1,256 @@
+extern crate cgmath;
+
+use std::vec::Vec;
+use std::io::IoResult;
+
+use cgmath::{Vector3, Basis3, Rotation, Rotation3, rad};
+
+use checkpoint::{invalid_data, write_uint, read_uint, write_vec3, read_vec3, write_uint_vec, read_uint_vec};
+
+/// Rotations applied during a single simulation step.
+#[deriving(Clone, Show)]
+pub struct StageRotation {
+    /// Euler pole of every plate.
+    pub euler_poles: Vec<Vector3<f32>>,
+    /// Angle every plate turned around its pole, in radians.
+    pub angles: Vec<f32>
+}
+
+/// Plate kinematics recorded over the course of a simulation: a rotation
+/// per plate and step, and plate membership whenever plates merge or
+/// split. Allows reconstructing point positions at any earlier step
+/// without storing snapshots. Points move with the rotation of their
+/// plate, so points slowed down near plate boundaries are reconstructed
+/// only approximately.
+pub struct RotationHistory {
+    /// Rotations of every step.
+    stages: Vec<StageRotation>,
+    /// Plate index of every point, along with the first step at which the
+    /// assignment is valid. Recorded only when plates change.
+    assignments: Vec<(uint, Vec<uint>)>
+}
+
+impl RotationHistory {
+    pub fn new(assignment: Vec<uint>) -> RotationHistory {
+        RotationHistory {
+            stages: Vec::new(),
+            assignments: vec![(0u, assignment)]
+        }
+    }
+
+    pub fn num_steps(&self) -> uint {
+        self.stages.len()
+    }
+
+    pub fn record_step(&mut self, stage: StageRotation) {
+        self.stages.push(stage);
+    }
+
+    /// Rotations applied during `step`, None if it was not simulated yet.
+    pub fn stage(&self, step: uint) -> Option<&StageRotation> {
+        if step < self.stages.len() {
+            Some(&self.stages[step])
+        } else {
+            None
+        }
+    }
+
+    /// Records a change of plate membership, effective from `first_step`.
+    pub fn record_assignment(&mut self,
+                             first_step: uint,
+                             assignment: Vec<uint>) {
+        match self.assignments.last() {
+            Some(&(last_step, _)) if last_step == first_step => {
+                self.assignments.pop();
+            },
+            _ => {}
+        }
+        self.assignments.push((first_step, assignment));
+    }
+
+    /// Plate index of every point at `step`.
+    pub fn assignment_at(&self, step: uint) -> &Vec<uint> {
+        let (_, ref first) = self.assignments[0];
+        let mut ret = first;
+        for &(first_step, ref assignment) in self.assignments.iter() {
+            if first_step > step {
+                break;
+            }
+            ret = assignment;
+        }
+        ret
+    }
+
+    /// Checks a history read from a checkpoint against the simulation it
+    /// belongs to.
+    pub fn validate(&self,
+                    num_points: uint,
+                    num_plates: uint,
+                    num_steps: uint) -> IoResult<()> {
+        if self.stages.len() != num_steps
+                || self.assignments.is_empty()
+                || self.stages.iter().any(|stage| stage.angles.len() != stage.euler_poles.len()) {
+            return Err(invalid_data("inconsistent rotation history"));
+        }
+
+        let mut prev_step = None;
+        for &(first_step, ref assignment) in self.assignments.iter() {
+            if prev_step.map_or(first_step != 0, |prev| first_step <= prev)
+                    || first_step > num_steps
+                    || assignment.len() != num_points {
+                return Err(invalid_data("inconsistent rotation history"));
+            }
+            prev_step = Some(first_step);
+        }
+
+        // every plate index must name a plate that existed at that step
+        for step in range(0u, num_steps + 1) {
+            let plates_at_step = match self.stage(step) {
+                Some(stage) => stage.euler_poles.len(),
+                None => num_plates
+            };
+            if self.assignment_at(step).iter().any(|&plate_idx| plate_idx >= plates_at_step) {
+                return Err(invalid_data("inconsistent rotation history"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Rotates `positions` from the end of `step` back to its start.
+    pub fn step_back(&self,
+                     positions: &mut Vec<Vector3<f32>>,
+                     step: uint) {
+        let stage = &self.stages[step];
+        let assignment = self.assignment_at(step);
+        let inverses: Vec<Basis3<f32>> =
+            range(0u, stage.euler_poles.len()).map(|plate_idx| {
+                Rotation3::from_axis_angle(&stage.euler_poles[plate_idx],
+                                           rad(-stage.angles[plate_idx]))
+            }).collect();
+
+        for i in range(0u, positions.len()) {
+            positions[i] = inverses[assignment[i]].rotate_vector(&positions[i]);
+        }
+    }
+
+    /// Rotates present-day `positions` back to where they were at `step`.
+    /// None if `step` is past the last recorded one.
+    pub fn reconstruct(&self,
+                       positions: &Vec<Vector3<f32>>,
+                       step: uint) -> Option<Vec<Vector3<f32>>> {
+        if step > self.num_steps() {
+            return None;
+        }
+
+        let mut ret = positions.clone();
+        for s in range(step, self.num_steps()).rev() {
+            self.step_back(&mut ret, s);
+        }
+
+        Some(ret)
+    }
+
+    pub fn write_to<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        try!(write_uint(w, self.stages.len()));
+        for stage in self.stages.iter() {
+            try!(write_uint(w, stage.euler_poles.len()));
+            for (pole, &angle) in stage.euler_poles.iter().zip(stage.angles.iter()) {
+                try!(write_vec3(w, pole));
+                try!(w.write_le_f32(angle));
+            }
+        }
+
+        try!(write_uint(w, self.assignments.len()));
+        for &(first_step, ref assignment) in self.assignments.iter() {
+            try!(write_uint(w, first_step));
+            try!(write_uint_vec(w, assignment));
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from<R: Reader>(r: &mut R) -> IoResult<RotationHistory> {
+        let num_steps = try!(read_uint(r));
+        let mut stages = Vec::new();
+        for _ in range(0u, num_steps) {
+            let num_plates = try!(read_uint(r));
+            let mut euler_poles = Vec::new();
+            let mut angles = Vec::new();
+            for _ in range(0u, num_plates) {
+                euler_poles.push(try!(read_vec3(r)));
+                angles.push(try!(r.read_le_f32()));
+            }
+            stages.push(StageRotation {
+                euler_poles: euler_poles,
+                angles: angles
+            });
+        }
+
+        let num_assignments = try!(read_uint(r));
+        let mut assignments = Vec::new();
+        for _ in range(0u, num_assignments) {
+            let first_step = try!(read_uint(r));
+            assignments.push((first_step, try!(read_uint_vec(r))));
+        }
+
+        Ok(RotationHistory {
+            stages: stages,
+            assignments: assignments
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use cgmath::{EuclideanVector, Vector, Vector3, Basis3, Rotation, Rotation3, rad};
+
+    use super::{RotationHistory, StageRotation};
+
+    fn rotate(pos: &Vector3<f32>, pole: &Vector3<f32>, angle: f32) -> Vector3<f32> {
+        let rot: Basis3<f32> = Rotation3::from_axis_angle(pole, rad(angle));
+        rot.rotate_vector(pos)
+    }
+
+    fn assert_close(a: &Vec<Vector3<f32>>, b: &Vec<Vector3<f32>>) {
+        for (p, q) in a.iter().zip(b.iter()) {
+            assert!(p.sub(q).length2() < 1.0e-10, "{} != {}", p, q);
+        }
+    }
+
+    #[test]
+    fn reconstructs_points_across_plate_changes() {
+        let poles = vec![Vector3::new(0.0f32, 0.0, 1.0), Vector3::new(1.0f32, 0.0, 0.0)];
+        let initial = vec![Vector3::new(1.0f32, 0.0, 0.0),
+                           Vector3::new(0.0f32, 1.0, 0.0),
+                           Vector3::new(0.0f32, 0.6, 0.8)];
+        let stages = vec![StageRotation { euler_poles: poles.clone(), angles: vec![0.1, 0.2] },
+                          StageRotation { euler_poles: poles.clone(), angles: vec![0.08, 0.3] }];
+        let assignments = vec![vec![0u, 0, 1], vec![0u, 1, 1]];
+
+        let mut history = RotationHistory::new(assignments[0].clone());
+        let mut snapshots = vec![initial.clone()];
+        let mut positions = initial;
+        for step in range(0u, 2) {
+            if step > 0 {
+                history.record_assignment(step, assignments[step].clone());
+            }
+            positions = range(0u, positions.len()).map(|i| {
+                let plate_idx = assignments[step][i];
+                rotate(&positions[i], &poles[plate_idx], stages[step].angles[plate_idx])
+            }).collect();
+            history.record_step(stages[step].clone());
+            snapshots.push(positions.clone());
+        }
+
+        for step in range(0u, 3) {
+            assert_close(&history.reconstruct(&positions, step).unwrap(), &snapshots[step]);
+        }
+        assert!(history.reconstruct(&positions, 3).is_none());
+
+        let mut stepped_back = positions.clone();
+        history.step_back(&mut stepped_back, 1);
+        assert_close(&stepped_back, &snapshots[1]);
+    }
+}
@@ -73,6 +73,10 @@ void main() {
 "
 };
 
+pub const OCEAN_COLOR: [f32, ..4] = [0.05, 0.15, 0.5, 1.0];
+pub const LAKE_COLOR: [f32, ..4] = [0.1, 0.25, 0.6, 1.0];
+pub const SALT_LAKE_COLOR: [f32, ..4] = [0.55, 0.6, 0.6, 1.0];
+
 pub fn color_for_hue(hue: f32) -> [f32, ..4] {
     let c = 0.5;
     let x = c * (1.0 - (hue % 2.0 - 1.0).abs());
@@ -96,6 +100,18 @@ pub fn color_by_height(height: f32, min_height: f32, max_height: f32) -> [f32, .
     color_for_hue(hue)
 }
 
+/// Ocean color, darker for deeper water.
+pub fn color_by_depth(depth: f32, max_depth: f32) -> [f32, ..4] {
+    const SHALLOW: [f32, ..3] = [0.2, 0.45, 0.75];
+    const DEEP: [f32, ..3] = [0.02, 0.08, 0.3];
+
+    let t = if max_depth > 0.0 { (depth / max_depth).min(1.0) } else { 0.0 };
+    [SHALLOW[0] + (DEEP[0] - SHALLOW[0]) * t,
+     SHALLOW[1] + (DEEP[1] - SHALLOW[1]) * t,
+     SHALLOW[2] + (DEEP[2] - SHALLOW[2]) * t,
+     1.0]
+}
+
 pub fn color_by_index(idx: uint,
                       max_idx: uint) -> [f32, ..4] {
     let hue = idx as f32 / max_idx as f32 * PI_2;
@@ -0,0 +1,178 @@
+use std::vec::Vec;
+use std::rand::Rng;
+use std::io::IoResult;
+
+use plate_simulation::{PlateSimulation, CrustType};
+
+/// Preset driving a plate simulation through a scripted history.
+#[deriving(Clone, PartialEq, Show)]
+pub enum Scenario {
+    Supercontinent
+}
+
+impl Scenario {
+    pub fn from_name(name: &str) -> Option<Scenario> {
+        match name {
+            "supercontinent" => Some(Scenario::Supercontinent),
+            _ => None
+        }
+    }
+}
+
+/// Convection interval used by the supercontinent scenario if none was
+/// configured. Without convection, rifted continents never reassemble.
+pub const SUPERCONTINENT_CONVECTION_INTERVAL: uint = 4;
+
+/// State of the continents after a single step.
+#[deriving(Clone, Show)]
+pub struct CycleSample {
+    pub step: uint,
+    pub time_myr: f32,
+    pub num_plates: uint,
+    pub num_landmasses: uint,
+    /// Fraction of continental crust belonging to the largest landmass.
+    pub largest_landmass_fraction: f32
+}
+
+impl CycleSample {
+    pub fn compute(sim: &PlateSimulation) -> CycleSample {
+        let landmasses = sim.continental_landmasses();
+        let continental = sim.verts.iter()
+                             .filter(|v| v.crust == CrustType::Continental)
+                             .count();
+        let largest = landmasses.iter().map(|l| l.len()).max().unwrap_or(0);
+
+        CycleSample {
+            step: sim.step(),
+            time_myr: sim.step() as f32 * sim.step_myr(),
+            num_plates: sim.num_plates(),
+            num_landmasses: landmasses.len(),
+            largest_landmass_fraction: if continental > 0 {
+                largest as f32 / continental as f32
+            } else {
+                0.0
+            }
+        }
+    }
+}
+
+/// Supercontinent cycle: an assembled supercontinent rifts after a random
+/// tenure, its fragments disperse, and mantle convection together with
+/// plate suturing eventually gathers them again.
+///
+/// The cycle tracks its phase and statistics, which are not stored in
+/// checkpoints, so scenario runs cannot be resumed.
+pub struct SupercontinentCycle {
+    /// Fraction of the planet surface covered by continental crust.
+    pub continental_fraction: f32,
+    /// Largest-landmass fraction at or above which continents count as
+    /// assembled.
+    pub assembled_fraction: f32,
+    /// Largest-landmass fraction at or below which continents count as
+    /// dispersed.
+    pub dispersed_fraction: f32,
+    /// Mean number of steps an assembled supercontinent survives before
+    /// rifting.
+    pub mean_tenure_steps: f32,
+    /// Rotation pulling rifted plates apart, in degrees per Myr.
+    pub rift_speed: f32,
+    assembled: bool,
+    num_dispersals: uint,
+    num_assemblies: uint,
+    samples: Vec<CycleSample>
+}
+
+impl SupercontinentCycle {
+    pub fn new() -> SupercontinentCycle {
+        SupercontinentCycle {
+            continental_fraction: 0.3,
+            assembled_fraction: 0.8,
+            dispersed_fraction: 0.5,
+            mean_tenure_steps: 8.0,
+            rift_speed: 0.5,
+            assembled: false,
+            num_dispersals: 0,
+            num_assemblies: 0,
+            samples: Vec::new()
+        }
+    }
+
+    /// Starts tracking `sim`. Continents that are already gathered do not
+    /// count as an assembly.
+    pub fn start(&mut self, sim: &PlateSimulation) {
+        let sample = CycleSample::compute(sim);
+        self.assembled = sample.largest_landmass_fraction >= self.assembled_fraction;
+        self.samples.push(sample);
+    }
+
+    /// Index of the plate carrying most continental crust.
+    fn largest_continental_plate(sim: &PlateSimulation) -> Option<uint> {
+        let mut counts = Vec::from_elem(sim.num_plates(), 0u);
+        for v in sim.verts.iter() {
+            if v.crust == CrustType::Continental {
+                counts[v.plate_idx] += 1;
+            }
+        }
+
+        range(0u, counts.len()).filter(|&i| counts[i] > 0)
+                               .max_by(|&i| counts[i])
+    }
+
+    /// Simulates a single step, rifting the supercontinent if its time has
+    /// come. `start` must be called first.
+    pub fn step<R: Rng>(&mut self,
+                        sim: &mut PlateSimulation,
+                        rng: &mut R) {
+        sim.simulate_plates(1);
+        let sample = CycleSample::compute(sim);
+
+        if sample.largest_landmass_fraction >= self.assembled_fraction {
+            if !self.assembled {
+                println!("supercontinent assembled at step {}", sample.step);
+                self.assembled = true;
+                self.num_assemblies += 1;
+            }
+
+            if rng.gen::<f32>() * self.mean_tenure_steps < 1.0 {
+                match SupercontinentCycle::largest_continental_plate(sim) {
+                    Some(plate_idx) => {
+                        match sim.split_plate(plate_idx, self.rift_speed, rng) {
+                            Some(new_idx) => println!("rifting plate {} into plates {} and {}",
+                                                      plate_idx, plate_idx, new_idx),
+                            None => {}
+                        }
+                    },
+                    None => {}
+                }
+            }
+        } else if self.assembled && sample.largest_landmass_fraction <= self.dispersed_fraction {
+            println!("supercontinent dispersed at step {}", sample.step);
+            self.assembled = false;
+            self.num_dispersals += 1;
+        }
+
+        self.samples.push(sample);
+    }
+
+    pub fn print_summary(&self) {
+        let fractions: Vec<f32> = self.samples.iter()
+                                      .map(|s| s.largest_landmass_fraction)
+                                      .collect();
+        let min_fraction = fractions.iter().fold(1.0f32, |a, &b| a.min(b));
+        let max_fraction = fractions.iter().fold(0.0f32, |a, &b| a.max(b));
+
+        println!("supercontinent cycle: {} dispersals, {} reassemblies in {} samples",
+                 self.num_dispersals, self.num_assemblies, self.samples.len());
+        println!("largest landmass fraction: min {}, max {}", min_fraction, max_fraction);
+    }
+
+    /// Writes the metrics of every step as CSV.
+    pub fn write_csv<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        try!(writeln!(w, "step,time_myr,num_plates,num_landmasses,largest_landmass_fraction"));
+        for s in self.samples.iter() {
+            try!(writeln!(w, "{},{},{},{},{}", s.step, s.time_myr, s.num_plates,
+                          s.num_landmasses, s.largest_landmass_fraction));
+        }
+        Ok(())
+    }
+}
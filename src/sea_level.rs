@@ -0,0 +1,141 @@
+use std::vec::Vec;
+use std::num::Float;
+
+use domain::Topology;
+
+/// How the height of the sea surface is chosen.
+#[deriving(Clone, PartialEq, Show)]
+pub enum SeaLevel {
+    /// Sea surface at a fixed distance from the planet center.
+    Absolute(f32),
+    /// Sea surface leaving the given fraction of vertices, in [0, 1], dry.
+    LandFraction(f32)
+}
+
+impl SeaLevel {
+    /// Height of the sea surface over vertices of given `heights`.
+    pub fn solve(&self, heights: &Vec<f32>) -> f32 {
+        match *self {
+            SeaLevel::Absolute(level) => level,
+            SeaLevel::LandFraction(fraction) => {
+                let mut sorted = heights.clone();
+                sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+                // every vertex above the sea level is land
+                let ocean_fraction = 1.0 - fraction.max(0.0).min(1.0);
+                let num_ocean = (ocean_fraction * sorted.len() as f32).round() as uint;
+                if num_ocean == 0 {
+                    sorted[0] - 1.0e-5
+                } else {
+                    sorted[num_ocean - 1]
+                }
+            }
+        }
+    }
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum SurfaceClass {
+    Ocean,
+    Land,
+    /// Land vertex next to the ocean, or face with both land and ocean
+    /// vertices.
+    Coast
+}
+
+impl SurfaceClass {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SurfaceClass::Ocean => "ocean",
+            SurfaceClass::Land => "land",
+            SurfaceClass::Coast => "coast"
+        }
+    }
+
+    pub fn to_idx(&self) -> uint {
+        match *self {
+            SurfaceClass::Ocean => 0,
+            SurfaceClass::Land => 1,
+            SurfaceClass::Coast => 2
+        }
+    }
+
+    /// Names of all classes, indexed by `to_idx`.
+    pub fn labels() -> Vec<String> {
+        vec![SurfaceClass::Ocean, SurfaceClass::Land, SurfaceClass::Coast]
+            .iter().map(|c| c.name().to_string()).collect()
+    }
+}
+
+/// Classifies vertices at or below `sea_level` as ocean, and land vertices
+/// with an ocean neighbor as coast.
+pub fn classify_vertices<T: Topology>(topology: &T,
+                                      heights: &Vec<f32>,
+                                      sea_level: f32) -> Vec<SurfaceClass> {
+    range(0u, heights.len()).map(|idx| {
+        if heights[idx] <= sea_level {
+            SurfaceClass::Ocean
+        } else if topology.neighbors(idx).iter().any(|&nbr| heights[nbr] <= sea_level) {
+            SurfaceClass::Coast
+        } else {
+            SurfaceClass::Land
+        }
+    }).collect()
+}
+
+/// Classifies a face by the classes of its vertices: ocean or land if all
+/// of them are ocean or all are dry, coast otherwise.
+pub fn classify_face(vertex_classes: &Vec<SurfaceClass>,
+                     vertex_indices: &[uint]) -> SurfaceClass {
+    let num_ocean = vertex_indices.iter()
+                                  .filter(|&&idx| vertex_classes[idx] == SurfaceClass::Ocean)
+                                  .count();
+    if num_ocean == vertex_indices.len() {
+        SurfaceClass::Ocean
+    } else if num_ocean == 0 {
+        SurfaceClass::Land
+    } else {
+        SurfaceClass::Coast
+    }
+}
+
+/// Land and ocean statistics of a world, with vertices standing for equal
+/// areas.
+#[deriving(Clone, Show)]
+pub struct SeaStats {
+    pub sea_level: f32,
+    /// Fraction of vertices above the sea level.
+    pub land_fraction: f32,
+    /// Depths below the sea level, in height units.
+    pub mean_ocean_depth: f32,
+    pub max_ocean_depth: f32
+}
+
+impl SeaStats {
+    pub fn compute(heights: &Vec<f32>, sea_level: f32) -> SeaStats {
+        let mut num_ocean = 0u;
+        let mut depth_sum = 0.0f32;
+        let mut max_depth = 0.0f32;
+
+        for &height in heights.iter() {
+            if height <= sea_level {
+                num_ocean += 1;
+                depth_sum += sea_level - height;
+                max_depth = max_depth.max(sea_level - height);
+            }
+        }
+
+        SeaStats {
+            sea_level: sea_level,
+            land_fraction: 1.0 - num_ocean as f32 / heights.len() as f32,
+            mean_ocean_depth: if num_ocean > 0 { depth_sum / num_ocean as f32 } else { 0.0 },
+            max_ocean_depth: max_depth
+        }
+    }
+
+    pub fn print(&self) {
+        println!("sea level {}: {}% land, ocean depth mean {}, max {}",
+                 self.sea_level, self.land_fraction * 100.0,
+                 self.mean_ocean_depth, self.max_ocean_depth);
+    }
+}
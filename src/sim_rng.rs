@@ -0,0 +1,63 @@
+use std::rand::{Rng, SeedableRng};
+use std::io::IoResult;
+
+use checkpoint::invalid_data;
+
+/// Xorshift random number generator producing the same sequence as
+/// `std::rand::XorShiftRng`, but with state that can be saved to and
+/// restored from a checkpoint.
+#[deriving(Clone, PartialEq, Show)]
+pub struct SimRng {
+    state: [u32, ..4]
+}
+
+impl Rng for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        let x = self.state[0];
+        let t = x ^ (x << 11);
+        self.state[0] = self.state[1];
+        self.state[1] = self.state[2];
+        self.state[2] = self.state[3];
+        let w = self.state[3];
+        self.state[3] = w ^ (w >> 19) ^ (t ^ (t >> 8));
+        self.state[3]
+    }
+}
+
+impl SeedableRng<[u32, ..4]> for SimRng {
+    fn reseed(&mut self, seed: [u32, ..4]) {
+        if seed.iter().all(|&x| x == 0) {
+            panic!("SimRng.reseed called with an all zero seed.");
+        }
+        self.state = seed;
+    }
+
+    fn from_seed(seed: [u32, ..4]) -> SimRng {
+        if seed.iter().all(|&x| x == 0) {
+            panic!("SimRng::from_seed called with an all zero seed.");
+        }
+        SimRng { state: seed }
+    }
+}
+
+impl SimRng {
+    pub fn write_to<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        for &x in self.state.iter() {
+            try!(w.write_le_u32(x));
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Reader>(r: &mut R) -> IoResult<SimRng> {
+        let mut state = [0u32, ..4];
+        for i in range(0u, 4) {
+            state[i] = try!(r.read_le_u32());
+        }
+
+        if state.iter().all(|&x| x == 0) {
+            return Err(invalid_data("invalid random number generator state"));
+        }
+
+        Ok(SimRng { state: state })
+    }
+}
@@ -0,0 +1,141 @@
+use std::vec::Vec;
+use std::num::Float;
+
+use domain::{Topology, PlateDomain};
+
+/// Flat, periodic grid of `width` x `height` points with unit spacing,
+/// each connected to its four nearest neighbors. Small enough to check
+/// boundary, collision and partition logic by hand.
+pub struct TorusGrid {
+    width: uint,
+    height: uint,
+    neighbors: Vec<Vec<uint>>,
+    edges: Vec<[uint, ..2]>,
+    /// Plate index of every point.
+    pub plates: Vec<uint>,
+    /// Velocity of every plate, in grid units per step.
+    pub plate_velocities: Vec<(f32, f32)>
+}
+
+impl TorusGrid {
+    /// Creates a grid covered by a single resting plate.
+    pub fn new(width: uint, height: uint) -> TorusGrid {
+        // smaller grids would connect points to the same neighbor twice
+        if width < 3 || height < 3 {
+            panic!("torus grid must be at least 3x3, got {}x{}", width, height);
+        }
+
+        let idx = |x: uint, y: uint| (y % height) * width + (x % width);
+        let mut neighbors = Vec::with_capacity(width * height);
+        let mut edges = Vec::with_capacity(width * height * 2);
+
+        for y in range(0u, height) {
+            for x in range(0u, width) {
+                neighbors.push(vec![idx(x + width - 1, y), idx(x + 1, y),
+                                    idx(x, y + height - 1), idx(x, y + 1)]);
+                edges.push([idx(x, y), idx(x + 1, y)]);
+                edges.push([idx(x, y), idx(x, y + 1)]);
+            }
+        }
+
+        TorusGrid {
+            width: width,
+            height: height,
+            neighbors: neighbors,
+            edges: edges,
+            plates: Vec::from_elem(width * height, 0u),
+            plate_velocities: vec![(0.0, 0.0)]
+        }
+    }
+
+    pub fn idx(&self, x: uint, y: uint) -> uint {
+        (y % self.height) * self.width + (x % self.width)
+    }
+
+    pub fn coords(&self, idx: uint) -> (uint, uint) {
+        (idx % self.width, idx / self.width)
+    }
+
+    /// Shortest displacement from point `a` to point `b`, wrapping around
+    /// the grid edges.
+    pub fn offset(&self, a: uint, b: uint) -> (f32, f32) {
+        let wrap = |d: int, size: uint| {
+            let size = size as int;
+            if d * 2 > size {
+                d - size
+            } else if d * 2 < -size {
+                d + size
+            } else {
+                d
+            }
+        };
+
+        let (ax, ay) = self.coords(a);
+        let (bx, by) = self.coords(b);
+        (wrap(bx as int - ax as int, self.width) as f32,
+         wrap(by as int - ay as int, self.height) as f32)
+    }
+
+    /// Assigns every point in the rectangle starting at (`x`, `y`) to `plate_idx`.
+    pub fn fill_plate(&mut self,
+                      x: uint,
+                      y: uint,
+                      width: uint,
+                      height: uint,
+                      plate_idx: uint) {
+        for dy in range(0u, height) {
+            for dx in range(0u, width) {
+                let idx = self.idx(x + dx, y + dy);
+                self.plates[idx] = plate_idx;
+            }
+        }
+    }
+}
+
+impl Topology for TorusGrid {
+    fn num_points(&self) -> uint {
+        self.width * self.height
+    }
+
+    fn neighbors(&self, idx: uint) -> &[uint] {
+        self.neighbors[idx].as_slice()
+    }
+
+    fn distance(&self, a: uint, b: uint) -> f32 {
+        let (dx, dy) = self.offset(a, b);
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    fn edge_length(&self) -> f32 {
+        1.0
+    }
+
+    fn num_edges(&self) -> uint {
+        self.edges.len()
+    }
+
+    fn edge(&self, edge_idx: uint) -> [uint, ..2] {
+        self.edges[edge_idx]
+    }
+}
+
+impl PlateDomain for TorusGrid {
+    fn plate_of(&self, idx: uint) -> uint {
+        self.plates[idx]
+    }
+
+    /// Speeds in grid units per step.
+    fn relative_motion(&self, a: uint, b: uint) -> Option<(f32, f32)> {
+        let (dx, dy) = self.offset(a, b);
+        let dist = (dx * dx + dy * dy).sqrt();
+        if dist == 0.0 {
+            return None;
+        }
+
+        let (vax, vay) = self.plate_velocities[self.plates[a]];
+        let (vbx, vby) = self.plate_velocities[self.plates[b]];
+        let (rx, ry) = (vax - vbx, vay - vby);
+
+        Some(((rx * dx + ry * dy) / dist, (rx * rx + ry * ry).sqrt()))
+    }
+}
@@ -3,6 +3,10 @@ extern crate gfx;
 
 use std::vec::Vec;
 use std::num::{Float, FloatMath};
+use std::sync::Arc;
+use std::collections::TreeMap;
+use std::io::IoResult;
+use std::rand::Rng;
 
 use cgmath::{EuclideanVector, Vector, Vector3, FixedArray};
 use gfx::batch::Context;
@@ -10,11 +14,47 @@ use gfx::{GlDevice, Device, DeviceHelper, ToSlice};
 
 use polyhedron::{Polyhedron};
 use rendering;
-use rendering::{PolyhedronBatch, Vertex, color_by_height};
+use rendering::{PolyhedronBatch, Vertex, color_by_height, color_by_depth,
+                LAKE_COLOR, SALT_LAKE_COLOR};
 use plate_simulation::PlateSimulation;
+use parallel;
+use domain::Topology;
+use erosion::{ThermalErosion, HydraulicErosion, ErosionReport};
+use hydrology::Drainage;
+use coastline::Coastlines;
+use landmass::{Regions, LandThresholds};
+use geo::triangle_area;
+use climate::{TemperatureModel, PrecipitationModel};
+use biome::BiomeTable;
+use geo::lat_lon_deg;
+use sea_level::{SeaLevel, SeaStats, SurfaceClass, classify_vertices, classify_face};
+use checkpoint::invalid_data;
+
+/// Height of the sea surface. `apply_heights` spreads vertex heights
+/// evenly around the unit sphere.
+pub const DEFAULT_SEA_LEVEL: f32 = 1.0;
+
+/// Category indices of the "lake" attribute.
+const NO_LAKE: uint = 0;
+const FRESH_LAKE: uint = 1;
+const SALT_LAKE: uint = 2;
+
+/// Category indices of the "biome" attribute, common to all biome tables.
+const OCEAN_BIOME: uint = 0;
+const LAKE_BIOME: uint = 1;
+
+/// Value attached to every world vertex.
+pub enum Attribute {
+    Scalar(Vec<f32>),
+    /// Category index of every vertex, and category labels.
+    Categorical(Vec<uint>, Vec<String>)
+}
 
 pub struct World {
-    poly: Polyhedron
+    poly: Polyhedron,
+    neighbors: Vec<Vec<uint>>,
+    sea_level: f32,
+    attributes: TreeMap<String, Attribute>
 }
 
 fn get_min_max_length<Iter: Iterator<Vector3<f32>>>(iter: &mut Iter) -> (f32, f32) {
@@ -34,9 +74,277 @@ fn get_min_max_length<Iter: Iterator<Vector3<f32>>>(iter: &mut Iter) -> (f32, f3
     (min_len_sq.sqrt(), max_len_sq.sqrt())
 }
 
+/// Data needed to compute height of world vertices, shared between threads.
+struct HeightInput {
+    world_positions: Vec<Vector3<f32>>,
+    /// Position and volcanic height of every plate simulation point.
+    plate_points: Vec<(Vector3<f32>, f32)>
+}
+
+fn height_delta(input: &HeightInput,
+                idx: uint) -> f32 {
+    const DOT_THRESHOLD: f32 = 0.1;
+
+    let pos = &input.world_positions[idx];
+    let mut delta = 0.0f32;
+    let mut nbr_count = 0u;
+    let mut nearest_dot = -1.0f32;
+    let mut volcanic_height = 0.0f32;
+
+    for &(ref plate_pos, plate_volcanic_height) in input.plate_points.iter() {
+        let dot = pos.dot(plate_pos);
+        if dot > DOT_THRESHOLD {
+            delta += dot;
+            nbr_count += 1;
+        }
+        if dot > nearest_dot {
+            nearest_dot = dot;
+            volcanic_height = plate_volcanic_height;
+        }
+    }
+
+    delta /= nbr_count as f32;
+    delta + volcanic_height
+}
+
 impl World {
     pub fn new(poly: Polyhedron) -> World {
-        World { poly: poly }
+        let neighbors = range(0u, poly.vertices.len()).map(|idx| {
+            poly.vertices[idx].edge_indices.iter().map(|&edge_idx| {
+                let edge = &poly.edges[edge_idx];
+                if edge.vertex_indices[0] == idx {
+                    edge.vertex_indices[1]
+                } else {
+                    edge.vertex_indices[0]
+                }
+            }).collect()
+        }).collect();
+
+        World {
+            poly: poly,
+            neighbors: neighbors,
+            sea_level: DEFAULT_SEA_LEVEL,
+            attributes: TreeMap::new()
+        }
+    }
+
+    /// Height of a vertex: its distance from the planet center.
+    pub fn height(&self, idx: uint) -> f32 {
+        self.poly.vertices[idx].pos.length()
+    }
+
+    pub fn heights(&self) -> Vec<f32> {
+        range(0u, self.poly.vertices.len()).map(|idx| self.height(idx)).collect()
+    }
+
+    /// Directions of all vertices.
+    pub fn positions(&self) -> Vec<Vector3<f32>> {
+        self.poly.vertices.iter().map(|v| v.pos.normalize()).collect()
+    }
+
+    pub fn set_heights(&mut self, heights: &Vec<f32>) {
+        for i in range(0u, self.poly.vertices.len()) {
+            let v = &mut self.poly.vertices[i].pos;
+            *v = v.normalize().mul_s(heights[i]);
+        }
+    }
+
+    /// Runs hydraulic erosion over vertex heights, with droplets placed by
+    /// `rng`.
+    pub fn erode_hydraulic<R: Rng>(&mut self,
+                                   erosion: &HydraulicErosion,
+                                   rng: &mut R) -> ErosionReport {
+        let mut heights = self.heights();
+        let report = erosion.apply(&*self, &mut heights, rng);
+        self.set_heights(&heights);
+        report
+    }
+
+    /// Runs thermal erosion over vertex heights. Returns the total height
+    /// moved.
+    pub fn erode_thermal(&mut self, erosion: &ThermalErosion) -> f32 {
+        let mut heights = self.heights();
+        let moved = erosion.apply(&*self, &mut heights);
+        self.set_heights(&heights);
+        moved
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes.get(&name.to_string())
+    }
+
+    pub fn set_attribute(&mut self, name: &str, attribute: Attribute) {
+        self.attributes.insert(name.to_string(), attribute);
+    }
+
+    pub fn sea_level(&self) -> f32 {
+        self.sea_level
+    }
+
+    /// Floods the world up to `sea_level` and stores the class of every
+    /// vertex as the "surface" attribute.
+    pub fn set_sea_level(&mut self, sea_level: &SeaLevel) -> SeaStats {
+        let heights = self.heights();
+        self.sea_level = sea_level.solve(&heights);
+
+        let classes = classify_vertices(&*self, &heights, self.sea_level);
+        let class_indices = classes.iter().map(|c| c.to_idx()).collect();
+        self.set_attribute("surface", Attribute::Categorical(class_indices,
+                                                             SurfaceClass::labels()));
+
+        SeaStats::compute(&heights, self.sea_level)
+    }
+
+    /// Ocean, land or coast class of every face.
+    pub fn face_classes(&self) -> Vec<SurfaceClass> {
+        let classes = classify_vertices(self, &self.heights(), self.sea_level);
+        self.poly.faces.iter()
+                       .map(|face| classify_face(&classes, face.vertex_indices.as_slice()))
+                       .collect()
+    }
+
+    /// Area around every vertex, as a fraction of the squared planet
+    /// radius: a third of every adjacent face.
+    pub fn vertex_areas(&self) -> Vec<f32> {
+        let mut areas = Vec::from_elem(self.poly.vertices.len(), 0.0f32);
+        for face in self.poly.faces.iter() {
+            let idx = face.vertex_indices;
+            let area = triangle_area(&self.poly.vertices[idx[0]].pos,
+                                     &self.poly.vertices[idx[1]].pos,
+                                     &self.poly.vertices[idx[2]].pos);
+            for &i in idx.iter() {
+                areas[i] += area / 3.0;
+            }
+        }
+        areas
+    }
+
+    /// Landmasses and water bodies at the current sea level.
+    pub fn regions(&self,
+                   planet_radius_km: f32,
+                   thresholds: &LandThresholds) -> Regions {
+        let is_land = self.heights().iter().map(|&h| h > self.sea_level).collect();
+        Regions::compute(self, &self.positions(), &self.vertex_areas(), &is_land,
+                         planet_radius_km, thresholds)
+    }
+
+    /// Coastlines at the current sea level, around landmasses of `regions`.
+    pub fn coastlines(&self,
+                      regions: &Regions,
+                      planet_radius_km: f32) -> Coastlines {
+        Coastlines::extract(&self.poly, &self.heights(), self.sea_level,
+                            &regions.landmass_of, &regions.water_body_of,
+                            regions.landmasses.len(),
+                            planet_radius_km)
+    }
+
+    /// Stores mean annual temperature of every vertex, in degrees Celsius,
+    /// as the "temperature" attribute.
+    pub fn set_temperature(&mut self,
+                           model: &TemperatureModel,
+                           planet_radius_km: f32) {
+        let temperature = model.apply(&*self, &self.positions(), &self.heights(),
+                                      self.sea_level, planet_radius_km);
+        self.set_attribute("temperature", Attribute::Scalar(temperature));
+    }
+
+    /// Stores annual precipitation of every vertex, in mm, as the
+    /// "precipitation" attribute. Needs the "temperature" attribute.
+    pub fn set_precipitation(&mut self, model: &PrecipitationModel) {
+        let precipitation = match self.attribute("temperature") {
+            Some(&Attribute::Scalar(ref temperature)) => {
+                model.apply(self, &self.positions(), &self.heights(),
+                            self.sea_level, temperature)
+            },
+            _ => panic!("temperature must be computed before precipitation")
+        };
+        self.set_attribute("precipitation", Attribute::Scalar(precipitation));
+    }
+
+    /// Stores the biome of every vertex as the "biome" attribute, with
+    /// categories labeled as in `table`. Needs the "temperature" and
+    /// "precipitation" attributes. Vertices of lakes found by `set_lakes`
+    /// get the lake category.
+    pub fn set_biomes(&mut self, table: &BiomeTable) {
+        let labels = table.labels();
+        let is_lake: Vec<bool> = match self.attribute("lake") {
+            Some(&Attribute::Categorical(ref lake_types, _)) => {
+                lake_types.iter().map(|&lake_type| lake_type != NO_LAKE).collect()
+            },
+            _ => Vec::from_elem(self.poly.vertices.len(), false)
+        };
+        let biomes = match (self.attribute("temperature"), self.attribute("precipitation")) {
+            (Some(&Attribute::Scalar(ref temperature)),
+             Some(&Attribute::Scalar(ref precipitation))) => {
+                let heights = self.heights();
+                range(0u, heights.len()).map(|idx| {
+                    if heights[idx] <= self.sea_level {
+                        OCEAN_BIOME
+                    } else if is_lake[idx] {
+                        LAKE_BIOME
+                    } else {
+                        table.classify(&labels, temperature[idx], precipitation[idx])
+                    }
+                }).collect()
+            },
+            _ => panic!("temperature and precipitation must be computed before biomes")
+        };
+        self.set_attribute("biome", Attribute::Categorical(biomes, labels));
+    }
+
+    /// Writes position and value of attribute `name` for every vertex.
+    /// Categorical values are written as labels. Fails if the attribute
+    /// was not computed.
+    pub fn write_attribute_csv<W: Writer>(&self,
+                                          name: &str,
+                                          w: &mut W) -> IoResult<()> {
+        let attribute = match self.attribute(name) {
+            Some(attribute) => attribute,
+            None => return Err(invalid_data("world attribute not computed"))
+        };
+
+        try!(writeln!(w, "vertex,lat,lon,{}", name));
+        for (idx, pos) in self.positions().iter().enumerate() {
+            let (lat, lon) = lat_lon_deg(pos);
+            match *attribute {
+                Attribute::Scalar(ref values) =>
+                    try!(writeln!(w, "{},{},{},{}", idx, lat, lon, values[idx])),
+                Attribute::Categorical(ref values, ref labels) =>
+                    try!(writeln!(w, "{},{},{},{}", idx, lat, lon, labels[values[idx]]))
+            }
+        }
+        Ok(())
+    }
+
+    /// Routes rain water falling evenly on every vertex down to the sea.
+    /// Vertices draining at least `river_threshold` vertices form rivers.
+    /// Lakes evaporating more than `lake_evaporation` per vertex of their
+    /// inflow turn salt.
+    pub fn drainage(&self,
+                    river_threshold: f32,
+                    lake_evaporation: f32) -> Drainage {
+        let runoff = Vec::from_elem(self.poly.vertices.len(), 1.0f32);
+        Drainage::compute(self, &self.heights(), self.sea_level, &runoff,
+                          river_threshold, lake_evaporation)
+    }
+
+    /// Stores lakes of `drainage` as the "lake" attribute (none, fresh or
+    /// salt) and the "water_surface" attribute: height of the lake surface
+    /// or of the ground, for vertices outside lakes.
+    pub fn set_lakes(&mut self, drainage: &Drainage) {
+        let mut lake_types = Vec::from_elem(self.poly.vertices.len(), NO_LAKE);
+        let mut water_surface = self.heights();
+
+        for lake in drainage.lakes.iter() {
+            for &idx in lake.vertices.iter() {
+                lake_types[idx] = if lake.salt { SALT_LAKE } else { FRESH_LAKE };
+                water_surface[idx] = lake.surface_height;
+            }
+        }
+
+        let labels = vec!["none".to_string(), "fresh".to_string(), "salt".to_string()];
+        self.set_attribute("lake", Attribute::Categorical(lake_types, labels));
+        self.set_attribute("water_surface", Attribute::Scalar(water_surface));
     }
 
     pub fn get_poly(&self) -> &Polyhedron {
@@ -46,6 +354,8 @@ impl World {
     fn get_vertices(&self) -> Vec<Vertex> {
         let poly = &self.poly;
         let (min_h, max_h) = get_min_max_length(&mut self.poly.vertices.iter().map(|v| v.pos));
+        let land_min_h = self.sea_level.max(min_h);
+        let face_classes = self.face_classes();
         let mut vertices = Vec::with_capacity(poly.faces.len() * 3u);
 
         for face_idx in range(0u, poly.faces.len()) {
@@ -55,9 +365,30 @@ impl World {
                          &poly.vertices[face.vertex_indices[2]].pos];
 
             let mean_pos = verts[0].add(verts[1]).add(verts[2]).div_s(3.0);
-            let face_col = color_by_height(mean_pos.length(), min_h, max_h);
+            let mut face_col = match face_classes[face_idx] {
+                SurfaceClass::Ocean => color_by_depth(self.sea_level - mean_pos.length(),
+                                                      self.sea_level - min_h),
+                _ => color_by_height(mean_pos.length().max(land_min_h), land_min_h, max_h)
+            };
+            let mut face_verts = [*verts[0], *verts[1], *verts[2]];
+
+            // faces entirely under water are drawn at the lake surface
+            match (self.attribute("lake"), self.attribute("water_surface")) {
+                (Some(&Attribute::Categorical(ref lakes, _)), Some(&Attribute::Scalar(ref surface))) => {
+                    let lake_type = lakes[face.vertex_indices[0]];
+                    if lake_type != NO_LAKE
+                            && face.vertex_indices.iter().all(|&idx| lakes[idx] == lake_type) {
+                        face_col = if lake_type == SALT_LAKE { SALT_LAKE_COLOR } else { LAKE_COLOR };
+                        for i in range(0u, 3) {
+                            face_verts[i] = face_verts[i].normalize()
+                                                .mul_s(surface[face.vertex_indices[i]]);
+                        }
+                    }
+                },
+                _ => {}
+            }
 
-            for &v in verts.iter() {
+            for v in face_verts.iter() {
                 vertices.push(Vertex {
                     pos: *v.as_fixed(),
                     color: face_col,
@@ -69,10 +400,60 @@ impl World {
         vertices
     }
 
+    /// Faces colored by vertex `categories`, using `colors` indexed by
+    /// category. Faces take the category shared by most of their vertices,
+    /// or of their first vertex if all differ.
+    fn get_category_vertices(&self,
+                             categories: &Vec<uint>,
+                             colors: &Vec<[f32, ..4]>) -> Vec<Vertex> {
+        let poly = &self.poly;
+        let mut vertices = Vec::with_capacity(poly.faces.len() * 3u);
+
+        for face_idx in range(0u, poly.faces.len()) {
+            let idx = poly.faces[face_idx].vertex_indices;
+            let category = if categories[idx[1]] == categories[idx[2]] {
+                categories[idx[1]]
+            } else {
+                categories[idx[0]]
+            };
+
+            for &i in idx.iter() {
+                vertices.push(Vertex {
+                    pos: *poly.vertices[i].pos.as_fixed(),
+                    color: colors[category],
+                    id: face_idx as i32
+                });
+            }
+        }
+
+        vertices
+    }
+
+    /// Batch drawing the world colored by categorical attribute `name`.
+    /// None if the world has no such attribute.
+    pub fn to_category_batch(&self,
+                             ctx: &mut Context,
+                             dev: &mut GlDevice,
+                             name: &str,
+                             colors: &Vec<[f32, ..4]>) -> Option<PolyhedronBatch> {
+        match self.attribute(name) {
+            Some(&Attribute::Categorical(ref categories, _)) => {
+                let vertices = self.get_category_vertices(categories, colors);
+                Some(World::make_batch(ctx, dev, vertices))
+            },
+            _ => None
+        }
+    }
+
     pub fn to_batch(&self,
                     ctx: &mut Context,
                     dev: &mut GlDevice) -> PolyhedronBatch {
-        let vertices = self.get_vertices();
+        World::make_batch(ctx, dev, self.get_vertices())
+    }
+
+    fn make_batch(ctx: &mut Context,
+                  dev: &mut GlDevice,
+                  vertices: Vec<Vertex>) -> PolyhedronBatch {
         let mesh = dev.create_mesh(vertices.as_slice());
 
         let indices = range(0u32, vertices.len() as u32).collect::<Vec<u32>>();
@@ -87,28 +468,18 @@ impl World {
     }
 
     pub fn apply_heights(&mut self,
-                         plate_sim: &PlateSimulation) {
-        const DOT_THRESHOLD: f32 = 0.1;
+                         plate_sim: &PlateSimulation,
+                         num_threads: uint) {
+        let input = HeightInput {
+            world_positions: self.poly.vertices.iter().map(|v| v.pos).collect(),
+            plate_points: plate_sim.verts.iter().map(|v| (v.pos, v.volcanic_height)).collect()
+        };
+        let deltas = parallel::map_indices(Arc::new(input), self.poly.vertices.len(),
+                                           num_threads, height_delta);
 
-        let mut deltas = Vec::with_capacity(self.poly.vertices.len());
-        let mut min_delta = deltas.len() as f32;
+        let mut min_delta = 0.0f32;
         let mut max_delta = 0.0f32;
-
-        for v in self.poly.vertices.iter() {
-            let mut delta = 0.0f32;
-            let mut nbr_count = 0u;
-
-            for v2 in plate_sim.verts.iter() {
-                let dot = v.pos.dot(&v2.pos);
-                if dot > DOT_THRESHOLD {
-                    delta += dot;
-                    nbr_count += 1;
-                }
-            }
-
-            delta /= nbr_count as f32;
-            deltas.push(delta);
-
+        for &delta in deltas.iter() {
             min_delta = min_delta.min(delta);
             max_delta = max_delta.max(delta);
         }
@@ -125,3 +496,33 @@ impl World {
     }
 }
 
+
+impl Topology for World {
+    fn num_points(&self) -> uint {
+        self.poly.vertices.len()
+    }
+
+    fn neighbors(&self, idx: uint) -> &[uint] {
+        self.neighbors[idx].as_slice()
+    }
+
+    /// Distance between vertex directions, ignoring their heights.
+    fn distance(&self, a: uint, b: uint) -> f32 {
+        self.poly.vertices[a].pos.normalize()
+            .sub(&self.poly.vertices[b].pos.normalize())
+            .length()
+    }
+
+    fn edge_length(&self) -> f32 {
+        let edge = &self.poly.edges[0];
+        self.distance(edge.vertex_indices[0], edge.vertex_indices[1])
+    }
+
+    fn num_edges(&self) -> uint {
+        self.poly.edges.len()
+    }
+
+    fn edge(&self, edge_idx: uint) -> [uint, ..2] {
+        self.poly.edges[edge_idx].vertex_indices
+    }
+}